@@ -0,0 +1,178 @@
+//! Snapshot and restore of the file descriptor table, for checkpoint/restore.
+//!
+//! Only files, directories and pipes can be restored for now. Other kinds of
+//! descriptors, such as sockets and raw devices, are skipped with a warning.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use axerrno::{AxError, AxResult};
+use axfs::{FS_CONTEXT, FileFlags, OpenOptions};
+use axio::{Seek, SeekFrom};
+
+use super::{ClosedFds, Directory, FD_TABLE, File, FileDescriptor, FileLike, Pipe};
+
+/// Type-specific state of a checkpointed descriptor.
+#[derive(Debug, Clone)]
+pub enum FdState {
+    /// A file on a filesystem, reopened by path.
+    File {
+        path: String,
+        read: bool,
+        write: bool,
+        append: bool,
+        /// Opened with `O_PATH`.
+        path_only: bool,
+        offset: u64,
+    },
+    /// A directory, reopened by path with its `getdents64` cursor.
+    Directory { path: String, offset: u64 },
+    /// One end of a pipe.
+    ///
+    /// Both ends of the same pipe carry the same `pair_id`, which is used to
+    /// reconnect them on restore. The buffered data is stored with each end.
+    Pipe {
+        pair_id: usize,
+        read_side: bool,
+        capacity: usize,
+        contents: Vec<u8>,
+    },
+}
+
+/// A checkpointed entry of the file descriptor table.
+#[derive(Debug, Clone)]
+pub struct FdSnapshot {
+    pub fd: usize,
+    pub cloexec: bool,
+    pub nonblocking: bool,
+    pub state: FdState,
+}
+
+fn snapshot_file_like(f: &Arc<dyn FileLike>) -> AxResult<Option<FdState>> {
+    if let Some(file) = f.downcast_ref::<File>() {
+        if file.memfd().is_some() {
+            return Ok(None);
+        }
+        let inner = file.inner();
+        return Ok(Some(FdState::File {
+            path: file.path().into_owned(),
+            read: inner.access(FileFlags::READ).is_ok(),
+            write: inner.access(FileFlags::WRITE).is_ok(),
+            append: inner.access(FileFlags::APPEND).is_ok(),
+            path_only: file.is_path(),
+            offset: if file.is_path() {
+                0
+            } else {
+                inner.seek(SeekFrom::Current(0))?
+            },
+        }));
+    }
+    if let Some(dir) = f.downcast_ref::<Directory>() {
+        return Ok(Some(FdState::Directory {
+            path: dir.path().into_owned(),
+            offset: *dir.offset.lock(),
+        }));
+    }
+    if let Some(pipe) = f.downcast_ref::<Pipe>() {
+        return Ok(Some(FdState::Pipe {
+            pair_id: pipe.pair_id(),
+            read_side: pipe.is_read(),
+            capacity: pipe.capacity(),
+            contents: pipe.buffered(),
+        }));
+    }
+    Ok(None)
+}
+
+/// Captures the current file descriptor table.
+pub fn snapshot_fd_table() -> AxResult<Vec<FdSnapshot>> {
+    let table = FD_TABLE.read();
+    let mut snapshot = Vec::new();
+    for fd in table.ids() {
+        let Some(desc) = table.get(fd) else {
+            continue;
+        };
+        match snapshot_file_like(&desc.inner)? {
+            Some(state) => snapshot.push(FdSnapshot {
+                fd,
+                cloexec: desc.cloexec,
+                nonblocking: desc.inner.nonblocking(),
+                state,
+            }),
+            None => warn!("checkpoint: skipping fd {fd} ({})", desc.inner.path()),
+        }
+    }
+    Ok(snapshot)
+}
+
+fn restore_file(
+    path: &str,
+    (read, write, append, path_only): (bool, bool, bool, bool),
+    offset: u64,
+) -> AxResult<Arc<dyn FileLike>> {
+    let mut options = OpenOptions::new();
+    options.read(read).write(write).append(append).path(path_only);
+    let file = options.open(&FS_CONTEXT.lock(), path)?.into_file()?;
+    if path_only {
+        return Ok(Arc::new(File::new_path(file)));
+    }
+    let file = File::new(file);
+    file.inner().seek(SeekFrom::Start(offset))?;
+    Ok(Arc::new(file))
+}
+
+/// Rebuilds the descriptors of `snapshot` in the current file descriptor
+/// table, replacing whatever occupies their numbers.
+///
+/// A pipe end whose peer is not part of the snapshot is restored with its
+/// peer already closed.
+pub fn restore_fd_table(snapshot: &[FdSnapshot]) -> AxResult<()> {
+    // Restored pipe ends by `(pair_id, read_side)`, so that duplicated ends
+    // share one open file and the peer of each pipe is found again
+    let mut pipe_ends: BTreeMap<(usize, bool), Arc<dyn FileLike>> = BTreeMap::new();
+
+    let mut restored = Vec::with_capacity(snapshot.len());
+    for entry in snapshot {
+        let f: Arc<dyn FileLike> = match &entry.state {
+            FdState::File {
+                path,
+                read,
+                write,
+                append,
+                path_only,
+                offset,
+            } => restore_file(path, (*read, *write, *append, *path_only), *offset)?,
+            FdState::Directory { path, offset } => {
+                let dir = Directory::new(FS_CONTEXT.lock().resolve(path)?);
+                *dir.offset.lock() = *offset;
+                Arc::new(dir)
+            }
+            FdState::Pipe {
+                pair_id,
+                read_side,
+                capacity,
+                contents,
+            } => {
+                if !pipe_ends.contains_key(&(*pair_id, *read_side)) {
+                    let (read_end, write_end) = Pipe::with_contents(*capacity, contents)?;
+                    pipe_ends.insert((*pair_id, true), Arc::new(read_end));
+                    pipe_ends.insert((*pair_id, false), Arc::new(write_end));
+                }
+                pipe_ends[&(*pair_id, *read_side)].clone()
+            }
+        };
+        f.set_nonblocking(entry.nonblocking)?;
+        restored.push((entry.fd, f, entry.cloexec));
+    }
+    // Ends that no descriptor claimed are closed here
+    drop(pipe_ends);
+
+    let mut closed = ClosedFds::default();
+    let mut table = FD_TABLE.write();
+    for (fd, inner, cloexec) in restored {
+        closed.take(&mut table, fd);
+        table
+            .add_at(fd, FileDescriptor { inner, cloexec })
+            .map_err(|_| AxError::TooManyOpenFiles)?;
+    }
+    Ok(())
+}
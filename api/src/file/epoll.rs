@@ -364,6 +364,13 @@ impl Epoll {
 
     pub fn poll_events(&self, out: &mut [epoll_event]) -> AxResult<usize> {
         trace!("Epoll: poll_events called, out.len()={}", out.len());
+        // Interests on files whose last reference is gone are dropped, even if
+        // they never became ready again
+        self.inner
+            .interests
+            .lock()
+            .retain(|key, _| key.file.strong_count() > 0);
+
         let mut count = 0;
         loop {
             let weak_interest = {
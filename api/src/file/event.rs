@@ -10,6 +10,13 @@ use axtask::future::{block_on, poll_io};
 
 use crate::file::{FileLike, IoDst, IoSrc};
 
+/// An `eventfd` object.
+///
+/// Reading a zero counter parks the reader on `poll_rx` until a writer adds a
+/// nonzero value; writing a value that would overflow the counter parks the
+/// writer on `poll_tx` until a reader drains it. Every successful operation
+/// wakes all waiters on the opposite side, which then race to consume the
+/// counter, as Linux does.
 pub struct EventFd {
     count: AtomicU64,
     semaphore: bool,
@@ -84,7 +91,11 @@ impl FileLike for EventFd {
                 });
             match result {
                 Ok(_) => {
-                    self.poll_rx.wake();
+                    // Adding zero cannot make the counter readable, so don't
+                    // bother waking readers that would just go back to sleep.
+                    if value > 0 {
+                        self.poll_rx.wake();
+                    }
                     Ok(size_of::<u64>())
                 }
                 Err(_) => Err(AxError::WouldBlock),
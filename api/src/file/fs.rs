@@ -1,20 +1,30 @@
-use alloc::{borrow::Cow, string::ToString, sync::Arc};
+use alloc::{borrow::Cow, collections::BTreeMap, format, string::ToString, sync::Arc};
 use core::{
     ffi::c_int,
     hint::likely,
     sync::atomic::{AtomicBool, Ordering},
     task::Context,
+    time::Duration,
 };
 
 use axerrno::{AxError, AxResult};
-use axfs::{FS_CONTEXT, FsContext};
-use axfs_ng_vfs::{Location, Metadata, NodeFlags};
+use axfs::{FS_CONTEXT, FileFlags, FsContext};
+use axfs_ng_vfs::{Location, Metadata, NodeFlags, NodeType};
+use axio::{IoBuf, Read, Seek, SeekFrom};
 use axpoll::{IoEvents, Pollable};
 use axsync::Mutex;
 use axtask::future::{block_on, poll_io};
-use linux_raw_sys::general::{AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW};
+use linux_raw_sys::general::{
+    AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE,
+    IN_CLOSE_WRITE, IN_MODIFY,
+};
 
-use super::{FileLike, Kstat, get_file_like};
+use super::{
+    FileLike, Kstat, Statfs, get_file_like,
+    inotify::{notify, notify_file},
+    lock::funlock_all,
+    memfd::Memfd,
+};
 use crate::file::{IoDst, IoSrc};
 
 pub fn with_fs<R>(dirfd: c_int, f: impl FnOnce(&mut FsContext) -> AxResult<R>) -> AxResult<R> {
@@ -27,6 +37,29 @@ pub fn with_fs<R>(dirfd: c_int, f: impl FnOnce(&mut FsContext) -> AxResult<R>) -
     }
 }
 
+/// Maximum number of symlinks followed while resolving a single path, matching
+/// Linux's `MAXSYMLINKS`.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+/// Resolves `path` like [`FsContext::resolve`], but follows a trailing symlink
+/// at most [`MAX_SYMLINK_FOLLOWS`] times and fails with `ELOOP` beyond that.
+pub fn resolve_follow(fs: &FsContext, path: &str) -> AxResult<Location> {
+    let mut loc = fs.resolve_no_follow(path)?;
+    for _ in 0..MAX_SYMLINK_FOLLOWS {
+        if loc.node_type() != NodeType::Symlink {
+            return Ok(loc);
+        }
+        let target = loc.read_link()?;
+        // Relative targets are looked up from the directory holding the link
+        let dir = loc.parent().ok_or(AxError::NotFound)?;
+        loc = fs.with_current_dir(dir)?.resolve_no_follow(&target)?;
+    }
+    if loc.node_type() == NodeType::Symlink {
+        return Err(AxError::FilesystemLoop);
+    }
+    Ok(loc)
+}
+
 pub enum ResolveAtResult {
     File(Location),
     Other(Arc<dyn FileLike>),
@@ -42,10 +75,17 @@ impl ResolveAtResult {
 
     pub fn stat(&self) -> AxResult<Kstat> {
         match self {
-            Self::File(file) => file.metadata().map(|it| metadata_to_kstat(&it)),
+            Self::File(file) => location_to_kstat(file),
             Self::Other(file_like) => file_like.stat(),
         }
     }
+
+    pub fn statfs(&self) -> AxResult<Statfs> {
+        match self {
+            Self::File(file) => location_to_statfs(file),
+            Self::Other(file_like) => file_like.statfs(),
+        }
+    }
 }
 
 pub fn resolve_at(dirfd: c_int, path: Option<&str>, flags: u32) -> AxResult<ResolveAtResult> {
@@ -54,6 +94,10 @@ pub fn resolve_at(dirfd: c_int, path: Option<&str>, flags: u32) -> AxResult<Reso
             if flags & AT_EMPTY_PATH == 0 {
                 return Err(AxError::NotFound);
             }
+            // An empty path operates on `dirfd` itself, which may be the cwd
+            if dirfd == AT_FDCWD {
+                return Ok(ResolveAtResult::File(FS_CONTEXT.lock().current_dir().clone()));
+            }
             let file_like = get_file_like(dirfd)?;
             let f = file_like.clone();
             Ok(if let Some(file) = f.downcast_ref::<File>() {
@@ -68,7 +112,7 @@ pub fn resolve_at(dirfd: c_int, path: Option<&str>, flags: u32) -> AxResult<Reso
             if flags & AT_SYMLINK_NOFOLLOW != 0 {
                 fs.resolve_no_follow(path)
             } else {
-                fs.resolve(path)
+                resolve_follow(fs, path)
             }
             .map(ResolveAtResult::File)
         }),
@@ -96,10 +140,79 @@ pub fn metadata_to_kstat(metadata: &Metadata) -> Kstat {
     }
 }
 
+/// Link counts of directories on filesystems that do not track them, by
+/// device and inode, along with the modification time they were counted at.
+static DIR_NLINKS: Mutex<BTreeMap<(u64, u64), (Duration, u32)>> = Mutex::new(BTreeMap::new());
+
+/// Drops the cached directory link counts, once a directory has been
+/// created, removed or moved.
+pub fn forget_dir_nlinks() {
+    DIR_NLINKS.lock().clear();
+}
+
+/// Builds the [`Kstat`] of `loc`.
+///
+/// Directories have one link from their parent, one from `.` and one from
+/// each subdirectory's `..`. Filesystems that do not track this report fewer
+/// than two links, in which case the count is rebuilt from the entries and
+/// cached until the directory changes.
+pub fn location_to_kstat(loc: &Location) -> AxResult<Kstat> {
+    let metadata = loc.metadata()?;
+    let mut stat = metadata_to_kstat(&metadata);
+    if metadata.node_type == NodeType::Directory && metadata.nlink < 2 {
+        let key = (stat.dev, stat.ino);
+        if let Some(&(mtime, nlink)) = DIR_NLINKS.lock().get(&key)
+            && mtime == stat.mtime
+        {
+            stat.nlink = nlink;
+            return Ok(stat);
+        }
+
+        let mut subdirs = 0;
+        let mut offset = 0;
+        loop {
+            let read = loc.read_dir(offset, &mut |name: &str, _ino, node_type, next| {
+                if node_type == NodeType::Directory && name != "." && name != ".." {
+                    subdirs += 1;
+                }
+                offset = next;
+                true
+            })?;
+            if read == 0 {
+                break;
+            }
+        }
+        stat.nlink = 2 + subdirs;
+        DIR_NLINKS.lock().insert(key, (stat.mtime, stat.nlink));
+    }
+    Ok(stat)
+}
+
+pub fn location_to_statfs(loc: &Location) -> AxResult<Statfs> {
+    let stat = loc.filesystem().stat()?;
+    Ok(Statfs {
+        fs_type: stat.fs_type as _,
+        block_size: stat.block_size as _,
+        blocks: stat.blocks as _,
+        blocks_free: stat.blocks_free as _,
+        blocks_available: stat.blocks_available as _,
+        files: stat.file_count as _,
+        files_free: stat.free_file_count as _,
+        // TODO: fsid
+        fsid: loc.mountpoint().device() << 32,
+        name_length: stat.name_length as _,
+        fragment_size: stat.fragment_size as _,
+        flags: stat.mount_flags as _,
+    })
+}
+
 /// File wrapper for `axfs::fops::File`.
 pub struct File {
     inner: axfs::File,
     nonblock: AtomicBool,
+    memfd: Option<Memfd>,
+    /// Opened with `O_PATH`: the file only references the node.
+    path_only: bool,
 }
 
 impl File {
@@ -107,6 +220,23 @@ impl File {
         Self {
             inner,
             nonblock: AtomicBool::new(false),
+            memfd: None,
+            path_only: false,
+        }
+    }
+
+    /// Creates an `O_PATH` file, which rejects any I/O with `EBADF`.
+    pub fn new_path(inner: axfs::File) -> Self {
+        Self {
+            path_only: true,
+            ..Self::new(inner)
+        }
+    }
+
+    pub(super) fn new_memfd(inner: axfs::File, memfd: Memfd) -> Self {
+        Self {
+            memfd: Some(memfd),
+            ..Self::new(inner)
         }
     }
 
@@ -114,11 +244,65 @@ impl File {
         &self.inner
     }
 
+    /// Returns the `memfd` state if this file was created by `memfd_create`.
+    pub fn memfd(&self) -> Option<&Memfd> {
+        self.memfd.as_ref()
+    }
+
+    /// Returns whether the file was opened with `O_PATH`.
+    pub fn is_path(&self) -> bool {
+        self.path_only
+    }
+
+    /// Writes `src` at `offset` without moving the file position. Positional
+    /// writes all go through here, so that the seals of a memfd are honored.
+    pub fn write_at(&self, src: impl Read + IoBuf, offset: u64) -> AxResult<usize> {
+        self.check_io()?;
+        if let Some(memfd) = &self.memfd {
+            let end = offset
+                .checked_add(src.remaining() as u64)
+                .ok_or(AxError::FileTooLarge)?;
+            memfd.check_write(self.inner.location().len()?, end)?;
+        }
+        let written = self.inner.write_at(src, offset)?;
+        if written > 0 {
+            self.notify_watches(IN_MODIFY);
+        }
+        Ok(written)
+    }
+
+    /// Reports `mask` to the inotify watches of the file and of its directory.
+    pub fn notify_watches(&self, mask: u32) {
+        let loc = self.inner.location();
+        if self.memfd.is_some() {
+            // A memfd is not reachable through any directory
+            notify(loc, mask, 0, None);
+        } else {
+            notify_file(loc, mask);
+        }
+    }
+
+    fn check_io(&self) -> AxResult {
+        if self.path_only {
+            return Err(AxError::BadFileDescriptor);
+        }
+        Ok(())
+    }
+
     fn is_blocking(&self) -> bool {
         self.inner.location().flags().contains(NodeFlags::BLOCKING)
     }
 }
 
+impl Drop for File {
+    fn drop(&mut self) {
+        funlock_all(self as *const _ as usize);
+        if !self.path_only && self.inner.access(FileFlags::WRITE).is_ok() {
+            self.notify_watches(IN_CLOSE_WRITE);
+        }
+    }
+}
+
 fn path_for(loc: &Location) -> Cow<'static, str> {
     loc.absolute_path()
         .map_or_else(|_| "<error>".into(), |f| Cow::Owned(f.to_string()))
@@ -126,6 +310,7 @@ fn path_for(loc: &Location) -> Cow<'static, str> {
 
 impl FileLike for File {
     fn read(&self, dst: &mut IoDst) -> AxResult<usize> {
+        self.check_io()?;
         let inner = self.inner();
         if likely(self.is_blocking()) {
             inner.read(dst)
@@ -137,21 +322,35 @@ impl FileLike for File {
     }
 
     fn write(&self, src: &mut IoSrc) -> AxResult<usize> {
+        self.check_io()?;
         let inner = self.inner();
-        if likely(self.is_blocking()) {
-            inner.write(src)
+        if let Some(memfd) = &self.memfd {
+            let end = inner.seek(SeekFrom::Current(0))? + src.remaining() as u64;
+            memfd.check_write(inner.location().len()?, end)?;
+        }
+        let written = if likely(self.is_blocking()) {
+            inner.write(src)?
         } else {
             block_on(poll_io(self, IoEvents::OUT, self.nonblocking(), || {
                 inner.write(&mut *src)
-            }))
+            }))?
+        };
+        if written > 0 {
+            self.notify_watches(IN_MODIFY);
         }
+        Ok(written)
     }
 
     fn stat(&self) -> AxResult<Kstat> {
-        Ok(metadata_to_kstat(&self.inner().location().metadata()?))
+        location_to_kstat(self.inner().location())
+    }
+
+    fn statfs(&self) -> AxResult<Statfs> {
+        location_to_statfs(self.inner().location())
     }
 
     fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+        self.check_io()?;
         self.inner().backend()?.location().ioctl(cmd, arg)
     }
 
@@ -164,8 +363,47 @@ impl FileLike for File {
         self.nonblock.load(Ordering::Acquire)
     }
 
+    fn fallocate(&self, mode: u32, offset: u64, len: u64) -> AxResult {
+        self.check_io()?;
+        let file = self.inner.access(FileFlags::WRITE)?;
+        let size = file.location().len()?;
+        let end = offset.checked_add(len).ok_or(AxError::InvalidInput)?;
+        match mode {
+            0 => {
+                let new_size = size.max(end);
+                if let Some(memfd) = &self.memfd {
+                    memfd.check_resize(size, new_size)?;
+                }
+                file.set_len(new_size)?;
+            }
+            // Blocks are allocated on write, there is nothing to reserve.
+            FALLOC_FL_KEEP_SIZE => {}
+            _ if mode == FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE => {
+                if let Some(memfd) = &self.memfd {
+                    memfd.check_write(size, offset)?;
+                }
+                // Holes read back as zeros, so zero-filling the range within the
+                // file is equivalent, if not as frugal.
+                let zeros = [0; 4096];
+                let mut pos = offset;
+                let end = end.min(size);
+                while pos < end {
+                    let chunk = (end - pos).min(zeros.len() as u64) as usize;
+                    pos += self.inner.write_at(&zeros[..chunk], pos)? as u64;
+                }
+            }
+            _ if mode & FALLOC_FL_PUNCH_HOLE != 0 => return Err(AxError::InvalidInput),
+            _ => return Err(AxError::OperationNotSupported),
+        }
+        self.notify_watches(IN_MODIFY);
+        Ok(())
+    }
+
     fn path(&self) -> Cow<'_, str> {
-        path_for(self.inner.location())
+        match &self.memfd {
+            Some(memfd) => format!("/memfd:{}", memfd.name()).into(),
+            None => path_for(self.inner.location()),
+        }
     }
 
     fn from_fd(fd: c_int) -> AxResult<Arc<Self>>
@@ -209,6 +447,29 @@ impl Directory {
     pub fn inner(&self) -> &Location {
         &self.inner
     }
+
+    /// Moves the `getdents64` cursor.
+    ///
+    /// Offsets are the opaque `d_off` values handed out by the filesystem, so
+    /// only 0 (rewind) and previously returned offsets are meaningful.
+    /// Seeking relative to the end is not supported.
+    pub fn seek(&self, pos: SeekFrom) -> AxResult<u64> {
+        let mut offset = self.offset.lock();
+        *offset = match pos {
+            SeekFrom::Start(off) => off,
+            SeekFrom::Current(delta) => offset
+                .checked_add_signed(delta)
+                .ok_or(AxError::InvalidInput)?,
+            SeekFrom::End(_) => return Err(AxError::InvalidInput),
+        };
+        Ok(*offset)
+    }
+}
+
+impl Drop for Directory {
+    fn drop(&mut self) {
+        funlock_all(self as *const _ as usize);
+    }
 }
 
 impl FileLike for Directory {
@@ -221,7 +482,11 @@ impl FileLike for Directory {
     }
 
     fn stat(&self) -> AxResult<Kstat> {
-        Ok(metadata_to_kstat(&self.inner.metadata()?))
+        location_to_kstat(&self.inner)
+    }
+
+    fn statfs(&self) -> AxResult<Statfs> {
+        location_to_statfs(&self.inner)
     }
 
     fn path(&self) -> Cow<'_, str> {
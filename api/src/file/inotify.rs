@@ -0,0 +1,277 @@
+use alloc::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    ffi::c_int,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering},
+    task::Context,
+};
+
+use axerrno::{AxError, AxResult};
+use axfs_ng_vfs::{Location, path::Path};
+use axpoll::{IoEvents, PollSet, Pollable};
+use axsync::Mutex;
+use axtask::future::{block_on, poll_io};
+use linux_raw_sys::general::{IN_IGNORED, IN_MASK_ADD, IN_Q_OVERFLOW, inotify_event};
+
+use crate::file::{FileLike, IoDst, IoSrc, with_fs};
+
+/// The maximum number of queued events per instance, like Linux's default
+/// `max_queued_events`.
+const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// Identifies a watched node by `(dev, ino)`.
+type NodeKey = (u64, u64);
+
+struct Event {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    name: Option<Vec<u8>>,
+}
+
+impl Event {
+    /// The name is NUL-terminated and padded so that the next record stays
+    /// aligned.
+    fn name_len(&self) -> usize {
+        self.name.as_ref().map_or(0, |name| {
+            (name.len() + 1).next_multiple_of(size_of::<inotify_event>())
+        })
+    }
+
+    fn size(&self) -> usize {
+        size_of::<inotify_event>() + self.name_len()
+    }
+
+    fn write_to(&self, dst: &mut IoDst) -> AxResult<()> {
+        let mut buf = Vec::with_capacity(self.size());
+        buf.extend_from_slice(&self.wd.to_ne_bytes());
+        buf.extend_from_slice(&self.mask.to_ne_bytes());
+        buf.extend_from_slice(&self.cookie.to_ne_bytes());
+        buf.extend_from_slice(&(self.name_len() as u32).to_ne_bytes());
+        if let Some(name) = &self.name {
+            buf.extend_from_slice(name);
+        }
+        buf.resize(self.size(), 0);
+        dst.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+struct Watch {
+    node: NodeKey,
+    mask: u32,
+}
+
+/// An `inotify` instance.
+///
+/// Watches are keyed by the `(dev, ino)` of the watched node, and events are
+/// generated by the syscall layer through [`notify`] after it mutates the
+/// filesystem.
+pub struct Inotify {
+    watches: Mutex<BTreeMap<i32, Watch>>,
+    next_wd: AtomicI32,
+    events: Mutex<VecDeque<Event>>,
+    non_blocking: AtomicBool,
+    poll_rx: PollSet,
+}
+
+/// All live instances, so that [`notify`] can find the interested ones.
+static INSTANCES: Mutex<Vec<Weak<Inotify>>> = Mutex::new(Vec::new());
+/// The number of watches across all instances, to keep [`notify`] cheap when
+/// nobody is watching.
+static WATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+impl Inotify {
+    pub fn new() -> Arc<Self> {
+        let inotify = Arc::new(Self {
+            watches: Mutex::new(BTreeMap::new()),
+            next_wd: AtomicI32::new(1),
+            events: Mutex::new(VecDeque::new()),
+            non_blocking: AtomicBool::new(false),
+            poll_rx: PollSet::new(),
+        });
+        let mut instances = INSTANCES.lock();
+        instances.retain(|it| it.strong_count() > 0);
+        instances.push(Arc::downgrade(&inotify));
+        inotify
+    }
+
+    /// Adds or updates the watch on `loc`, returning its watch descriptor.
+    pub fn add_watch(&self, loc: &Location, mask: u32) -> AxResult<i32> {
+        let metadata = loc.metadata()?;
+        let node = (metadata.device, metadata.inode);
+        let mut watches = self.watches.lock();
+        if let Some((&wd, watch)) = watches.iter_mut().find(|(_, it)| it.node == node) {
+            if mask & IN_MASK_ADD != 0 {
+                watch.mask |= mask & !IN_MASK_ADD;
+            } else {
+                watch.mask = mask;
+            }
+            return Ok(wd);
+        }
+        let wd = self.next_wd.fetch_add(1, Ordering::Relaxed);
+        watches.insert(wd, Watch { node, mask });
+        WATCH_COUNT.fetch_add(1, Ordering::Relaxed);
+        Ok(wd)
+    }
+
+    /// Removes the watch `wd`, queueing an `IN_IGNORED` event for it.
+    pub fn rm_watch(&self, wd: i32) -> AxResult<()> {
+        self.watches.lock().remove(&wd).ok_or(AxError::InvalidInput)?;
+        WATCH_COUNT.fetch_sub(1, Ordering::Relaxed);
+        self.push(Event {
+            wd,
+            mask: IN_IGNORED,
+            cookie: 0,
+            name: None,
+        });
+        Ok(())
+    }
+
+    fn push(&self, event: Event) {
+        let mut events = self.events.lock();
+        if events.len() >= MAX_QUEUED_EVENTS {
+            if events.back().is_some_and(|it| it.mask == IN_Q_OVERFLOW) {
+                return;
+            }
+            events.push_back(Event {
+                wd: -1,
+                mask: IN_Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            });
+        } else {
+            events.push_back(event);
+        }
+        drop(events);
+        self.poll_rx.wake();
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        WATCH_COUNT.fetch_sub(self.watches.lock().len(), Ordering::Relaxed);
+    }
+}
+
+impl FileLike for Inotify {
+    fn read(&self, dst: &mut IoDst) -> AxResult<usize> {
+        block_on(poll_io(self, IoEvents::IN, self.nonblocking(), || {
+            let mut events = self.events.lock();
+            let Some(first) = events.front() else {
+                return Err(AxError::WouldBlock);
+            };
+            if first.size() > dst.remaining_mut() {
+                return Err(AxError::InvalidInput);
+            }
+            let mut read = 0;
+            while let Some(event) = events.front()
+                && event.size() <= dst.remaining_mut()
+            {
+                event.write_to(dst)?;
+                read += event.size();
+                events.pop_front();
+            }
+            Ok(read)
+        }))
+    }
+
+    fn write(&self, _src: &mut IoSrc) -> AxResult<usize> {
+        Err(AxError::InvalidInput)
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.non_blocking.load(Ordering::Acquire)
+    }
+
+    fn set_nonblocking(&self, non_blocking: bool) -> AxResult {
+        self.non_blocking.store(non_blocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn path(&self) -> Cow<'_, str> {
+        "anon_inode:inotify".into()
+    }
+}
+
+impl Pollable for Inotify {
+    fn poll(&self) -> IoEvents {
+        let mut events = IoEvents::empty();
+        events.set(IoEvents::IN, !self.events.lock().is_empty());
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        if events.contains(IoEvents::IN) {
+            self.poll_rx.register(context.waker());
+        }
+    }
+}
+
+/// Returns a fresh cookie to tie an `IN_MOVED_FROM` to its `IN_MOVED_TO`.
+pub fn next_cookie() -> u32 {
+    NEXT_COOKIE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Whether any watch exists at all. Lets callers skip extra lookups needed
+/// only to generate events.
+pub fn watching() -> bool {
+    WATCH_COUNT.load(Ordering::Relaxed) > 0
+}
+
+/// Reports `mask` on `loc` to every watch interested in it. `name` is the
+/// affected entry when `loc` is a directory.
+pub fn notify(loc: &Location, mask: u32, cookie: u32, name: Option<&str>) {
+    if !watching() {
+        return;
+    }
+    let Ok(metadata) = loc.metadata() else {
+        return;
+    };
+    let node = (metadata.device, metadata.inode);
+    let instances = INSTANCES.lock().clone();
+    for inotify in instances.iter().filter_map(Weak::upgrade) {
+        let matched = inotify
+            .watches
+            .lock()
+            .iter()
+            .filter(|(_, watch)| watch.node == node && watch.mask & mask != 0)
+            .map(|(&wd, _)| wd)
+            .collect::<Vec<_>>();
+        for wd in matched {
+            inotify.push(Event {
+                wd,
+                mask,
+                cookie,
+                name: name.map(|it| it.as_bytes().to_vec()),
+            });
+        }
+    }
+}
+
+/// Reports `mask` on the file at `loc` and on its directory, naming the file
+/// there.
+pub fn notify_file(loc: &Location, mask: u32) {
+    if !watching() {
+        return;
+    }
+    notify(loc, mask, 0, None);
+    if let Some(dir) = loc.parent() {
+        notify(&dir, mask, 0, Some(loc.name()));
+    }
+}
+
+/// Reports `mask` on the parent directory of `path`, naming the entry.
+pub fn notify_parent(dirfd: c_int, path: &str, mask: u32) {
+    if !watching() {
+        return;
+    }
+    if let Ok((dir, name)) = with_fs(dirfd, |fs| fs.resolve_parent(Path::new(path))) {
+        notify(&dir, mask, 0, Some(&*name));
+    }
+}
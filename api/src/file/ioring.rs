@@ -0,0 +1,231 @@
+//! A minimal batched I/O submission interface ("ioring").
+//!
+//! Each open of `/dev/ioring` yields an independent [`IoRing`]. Userspace
+//! allocates two rings in its own memory (e.g. with an anonymous `mmap`),
+//! registers them with [`IORING_SETUP`], posts [`IoRingSqe`]s to the
+//! submission ring and calls [`IORING_ENTER`] to have them processed in order.
+//! Every processed entry produces one [`IoRingCqe`] in the completion ring.
+//!
+//! # ABI
+//!
+//! Both rings start with a [`RingHeader`] followed by `entries` slots, where
+//! `entries` is a power of two. Slot `i` lives at index `i & (entries - 1)`.
+//! `head` and `tail` are free-running counters: the producer writes a slot and
+//! then bumps `tail`, the consumer reads a slot and then bumps `head`. The
+//! submission ring is produced by userspace and consumed by the kernel; the
+//! completion ring the other way around.
+
+use alloc::borrow::Cow;
+use core::{mem::offset_of, task::Context};
+
+use axerrno::{AxError, AxResult, LinuxError};
+use axpoll::{IoEvents, Pollable};
+use axsync::Mutex;
+use starry_vm::{VmMutPtr, VmPtr};
+
+use crate::{
+    file::{FileLike, close_file_like, get_file_like},
+    mm::{VmBytes, VmBytesMut},
+};
+
+/// `_IOW('r', 1, IoRingSetup)`: registers the rings.
+pub const IORING_SETUP: u32 = 0x4018_7201;
+/// `_IO('r', 2)`: processes up to `arg` submissions, returning the number of
+/// submissions consumed.
+pub const IORING_ENTER: u32 = 0x7202;
+
+/// The largest number of slots in a ring.
+const MAX_ENTRIES: u32 = 4096;
+
+/// Does nothing; completes with `res = 0`.
+pub const IORING_OP_NOP: u8 = 0;
+/// `read(fd, addr, len)`.
+pub const IORING_OP_READ: u8 = 1;
+/// `write(fd, addr, len)`.
+pub const IORING_OP_WRITE: u8 = 2;
+/// `close(fd)`.
+pub const IORING_OP_CLOSE: u8 = 3;
+
+/// The argument of [`IORING_SETUP`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoRingSetup {
+    /// Number of slots in each ring, a power of two up to 4096.
+    pub entries: u32,
+    pub _pad: u32,
+    /// User address of the submission ring.
+    pub sq_ring: u64,
+    /// User address of the completion ring.
+    pub cq_ring: u64,
+}
+
+/// The header at the start of each ring.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RingHeader {
+    pub head: u32,
+    pub tail: u32,
+}
+
+/// A submission queue entry.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoRingSqe {
+    /// One of the `IORING_OP_*` opcodes.
+    pub opcode: u8,
+    pub _pad: [u8; 3],
+    /// The file descriptor to operate on.
+    pub fd: i32,
+    /// User buffer address for reads and writes.
+    pub addr: u64,
+    /// User buffer length for reads and writes.
+    pub len: u32,
+    pub _pad2: u32,
+    /// Copied verbatim into the completion.
+    pub user_data: u64,
+}
+
+/// A completion queue entry.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoRingCqe {
+    /// The `user_data` of the submission.
+    pub user_data: u64,
+    /// The result of the operation, or a negated errno.
+    pub res: i32,
+    pub flags: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Rings {
+    mask: u32,
+    sq_ring: usize,
+    cq_ring: usize,
+}
+
+impl Rings {
+    fn header(ring: usize) -> AxResult<RingHeader> {
+        // FIXME: AnyBitPattern
+        Ok(unsafe { (ring as *const RingHeader).vm_read_uninit()?.assume_init() })
+    }
+
+    fn set_head(ring: usize, head: u32) -> AxResult<()> {
+        ((ring + offset_of!(RingHeader, head)) as *mut u32).vm_write(head)
+    }
+
+    fn set_tail(ring: usize, tail: u32) -> AxResult<()> {
+        ((ring + offset_of!(RingHeader, tail)) as *mut u32).vm_write(tail)
+    }
+
+    fn slot<T>(&self, ring: usize, index: u32) -> usize {
+        ring + size_of::<RingHeader>() + (index & self.mask) as usize * size_of::<T>()
+    }
+}
+
+/// An ioring instance. See the [module documentation](self).
+#[derive(Default)]
+pub struct IoRing {
+    rings: Mutex<Option<Rings>>,
+    /// Held for a whole [`IORING_ENTER`], so that concurrent calls do not pick
+    /// up the same submissions.
+    submit: Mutex<()>,
+}
+
+impl IoRing {
+    fn setup(&self, setup: IoRingSetup) -> AxResult<()> {
+        if !setup.entries.is_power_of_two() || setup.entries > MAX_ENTRIES {
+            return Err(AxError::InvalidInput);
+        }
+        let align = align_of::<IoRingSqe>() as u64;
+        if setup.sq_ring % align != 0 || setup.cq_ring % align != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        let mut rings = self.rings.lock();
+        if rings.is_some() {
+            return Err(AxError::ResourceBusy);
+        }
+        *rings = Some(Rings {
+            mask: setup.entries - 1,
+            sq_ring: setup.sq_ring as usize,
+            cq_ring: setup.cq_ring as usize,
+        });
+        Ok(())
+    }
+
+    fn enter(&self, to_submit: u32) -> AxResult<usize> {
+        let _submit = self.submit.lock();
+        // The descriptor is only addresses, so it is copied out rather than
+        // holding the lock across operations that may block.
+        let rings = self.rings.lock().ok_or(AxError::InvalidInput)?;
+
+        let sq = Rings::header(rings.sq_ring)?;
+        let cq = Rings::header(rings.cq_ring)?;
+        let pending = sq.tail.wrapping_sub(sq.head).min(to_submit);
+        let room = (rings.mask + 1).saturating_sub(cq.tail.wrapping_sub(cq.head));
+        let count = pending.min(room);
+        if pending > 0 && count == 0 {
+            // The completion ring is full; userspace has to reap first.
+            return Err(AxError::ResourceBusy);
+        }
+
+        for i in 0..count {
+            let sqe_ptr = rings.slot::<IoRingSqe>(rings.sq_ring, sq.head.wrapping_add(i));
+            // FIXME: AnyBitPattern
+            let sqe = unsafe { (sqe_ptr as *const IoRingSqe).vm_read_uninit()?.assume_init() };
+            let res = match execute(&sqe) {
+                Ok(n) => i32::try_from(n).unwrap_or(-LinuxError::EOVERFLOW.code()),
+                Err(err) => -LinuxError::from(err).code(),
+            };
+            let cqe_ptr = rings.slot::<IoRingCqe>(rings.cq_ring, cq.tail.wrapping_add(i));
+            (cqe_ptr as *mut IoRingCqe).vm_write(IoRingCqe {
+                user_data: sqe.user_data,
+                res,
+                flags: 0,
+            })?;
+            // Publish each completion as soon as it is written, and consume the
+            // submission only afterwards.
+            Rings::set_tail(rings.cq_ring, cq.tail.wrapping_add(i + 1))?;
+            Rings::set_head(rings.sq_ring, sq.head.wrapping_add(i + 1))?;
+        }
+        Ok(count as usize)
+    }
+}
+
+fn execute(sqe: &IoRingSqe) -> AxResult<usize> {
+    match sqe.opcode {
+        IORING_OP_NOP => Ok(0),
+        IORING_OP_READ => get_file_like(sqe.fd)?
+            .read(&mut VmBytesMut::new(sqe.addr as *mut u8, sqe.len as usize)),
+        IORING_OP_WRITE => get_file_like(sqe.fd)?
+            .write(&mut VmBytes::new(sqe.addr as *const u8, sqe.len as usize)),
+        IORING_OP_CLOSE => close_file_like(sqe.fd).map(|_| 0),
+        _ => Err(AxError::InvalidInput),
+    }
+}
+
+impl FileLike for IoRing {
+    fn path(&self) -> Cow<'_, str> {
+        "anon_inode:[ioring]".into()
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+        match cmd {
+            IORING_SETUP => {
+                // FIXME: AnyBitPattern
+                let setup = unsafe { (arg as *const IoRingSetup).vm_read_uninit()?.assume_init() };
+                self.setup(setup)?;
+                Ok(0)
+            }
+            IORING_ENTER => self.enter(arg as u32),
+            _ => Err(AxError::NotATty),
+        }
+    }
+}
+
+impl Pollable for IoRing {
+    fn poll(&self) -> IoEvents {
+        IoEvents::empty()
+    }
+
+    fn register(&self, _context: &mut Context<'_>, _events: IoEvents) {}
+}
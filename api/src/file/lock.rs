@@ -0,0 +1,240 @@
+//! Advisory `flock(2)` locks and `fcntl(2)` byte-range record locks.
+//!
+//! `flock` locks belong to an open file description, identified by the address
+//! of the shared file object, so `dup` and `fork` share them and the last close
+//! releases them. Record locks are owned either by a process (`F_SETLK`) or by
+//! an open file description (`F_OFD_SETLK`).
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{future::poll_fn, task::Poll};
+
+use axerrno::{AxError, AxResult};
+use axpoll::PollSet;
+use axsync::Mutex;
+use axtask::future::{block_on, interruptible};
+use lazy_static::lazy_static;
+use starry_process::Pid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlockKind {
+    Shared,
+    Exclusive,
+}
+
+/// Identifies a file node by `(dev, ino)`.
+pub type NodeKey = (u64, u64);
+
+/// Locks held on one node, by owning open file description.
+type Holders = BTreeMap<usize, FlockKind>;
+
+static FLOCKS: Mutex<BTreeMap<NodeKey, Holders>> = Mutex::new(BTreeMap::new());
+
+lazy_static! {
+    /// Woken whenever a lock is released.
+    static ref LOCK_RELEASED: PollSet = PollSet::new();
+}
+
+fn try_flock(node: NodeKey, owner: usize, kind: FlockKind) -> bool {
+    let mut table = FLOCKS.lock();
+    let conflict = table.get(&node).is_some_and(|holders| {
+        holders.iter().any(|(&other, &held)| {
+            other != owner && (kind == FlockKind::Exclusive || held == FlockKind::Exclusive)
+        })
+    });
+    if !conflict {
+        table.entry(node).or_default().insert(owner, kind);
+    }
+    !conflict
+}
+
+/// Acquires (or converts) the lock of `owner` on `node`.
+///
+/// Blocks until no other owner holds a conflicting lock, unless `nonblocking`
+/// is set, in which case [`AxError::WouldBlock`] is returned.
+pub fn flock(node: NodeKey, owner: usize, kind: FlockKind, nonblocking: bool) -> AxResult<()> {
+    if try_flock(node, owner, kind) {
+        return Ok(());
+    }
+    if nonblocking {
+        return Err(AxError::WouldBlock);
+    }
+    block_on(interruptible(poll_fn(|cx| {
+        LOCK_RELEASED.register(cx.waker());
+        if try_flock(node, owner, kind) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })))
+}
+
+/// Releases the lock of `owner` on `node`, if any.
+pub fn funlock(node: NodeKey, owner: usize) {
+    let mut table = FLOCKS.lock();
+    let Some(holders) = table.get_mut(&node) else {
+        return;
+    };
+    if holders.remove(&owner).is_some() {
+        if holders.is_empty() {
+            table.remove(&node);
+        }
+        LOCK_RELEASED.wake();
+    }
+}
+
+/// Releases every lock of the open file description `owner`, both `flock`
+/// and OFD record locks. Called when the open file description is dropped.
+pub fn funlock_all(owner: usize) {
+    let mut released = false;
+    FLOCKS.lock().retain(|_, holders| {
+        released |= holders.remove(&owner).is_some();
+        !holders.is_empty()
+    });
+    released |= release_record_locks(|_, lock| lock.owner == RecordOwner::File(owner));
+    if released {
+        LOCK_RELEASED.wake();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordOwner {
+    /// A traditional POSIX lock, released on any close of the file by the
+    /// process.
+    Process(Pid),
+    /// An open file description lock.
+    File(usize),
+}
+
+/// A byte-range lock over `[start, end)`.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordLock {
+    pub start: u64,
+    pub end: u64,
+    pub kind: FlockKind,
+    pub owner: RecordOwner,
+}
+
+impl RecordLock {
+    fn conflicts(&self, other: &RecordLock) -> bool {
+        self.owner != other.owner
+            && self.start < other.end
+            && other.start < self.end
+            && (self.kind == FlockKind::Exclusive || other.kind == FlockKind::Exclusive)
+    }
+}
+
+static RECORD_LOCKS: Mutex<BTreeMap<NodeKey, Vec<RecordLock>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the first lock on `node` that would conflict with `lock`.
+pub fn test_record_lock(node: NodeKey, lock: &RecordLock) -> Option<RecordLock> {
+    RECORD_LOCKS
+        .lock()
+        .get(&node)?
+        .iter()
+        .find(|held| held.conflicts(lock))
+        .copied()
+}
+
+/// Removes the range `[start, end)` from the locks of `owner`, splitting the
+/// locks that only partially overlap it.
+fn carve(locks: &mut Vec<RecordLock>, owner: RecordOwner, start: u64, end: u64) {
+    let mut rest = Vec::new();
+    locks.retain(|held| {
+        if held.owner != owner || held.end <= start || end <= held.start {
+            return true;
+        }
+        if held.start < start {
+            rest.push(RecordLock { end: start, ..*held });
+        }
+        if end < held.end {
+            rest.push(RecordLock { start: end, ..*held });
+        }
+        false
+    });
+    locks.extend(rest);
+}
+
+fn try_record_lock(node: NodeKey, lock: &RecordLock) -> bool {
+    let mut table = RECORD_LOCKS.lock();
+    if table
+        .get(&node)
+        .is_some_and(|locks| locks.iter().any(|held| held.conflicts(lock)))
+    {
+        return false;
+    }
+    let locks = table.entry(node).or_default();
+    carve(locks, lock.owner, lock.start, lock.end);
+    locks.push(*lock);
+    true
+}
+
+/// Acquires `lock` on `node`, replacing any locks of the same owner in its
+/// range.
+///
+/// Blocks until no conflicting lock is held, unless `nonblocking` is set, in
+/// which case [`AxError::WouldBlock`] is returned.
+pub fn set_record_lock(node: NodeKey, lock: RecordLock, nonblocking: bool) -> AxResult<()> {
+    if try_record_lock(node, &lock) {
+        return Ok(());
+    }
+    if nonblocking {
+        return Err(AxError::WouldBlock);
+    }
+    block_on(interruptible(poll_fn(|cx| {
+        LOCK_RELEASED.register(cx.waker());
+        if try_record_lock(node, &lock) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })))
+}
+
+/// Unlocks `[start, end)` on `node` for `owner`.
+pub fn unset_record_lock(node: NodeKey, owner: RecordOwner, start: u64, end: u64) {
+    let mut table = RECORD_LOCKS.lock();
+    let Some(locks) = table.get_mut(&node) else {
+        return;
+    };
+    carve(locks, owner, start, end);
+    if locks.is_empty() {
+        table.remove(&node);
+    }
+    drop(table);
+    LOCK_RELEASED.wake();
+}
+
+fn release_record_locks(mut pred: impl FnMut(&NodeKey, &RecordLock) -> bool) -> bool {
+    let mut released = false;
+    RECORD_LOCKS.lock().retain(|node, locks| {
+        let len = locks.len();
+        locks.retain(|lock| !pred(node, lock));
+        released |= locks.len() != len;
+        !locks.is_empty()
+    });
+    released
+}
+
+/// Whether process `pid` holds any POSIX record lock.
+pub fn holds_process_locks(pid: Pid) -> bool {
+    RECORD_LOCKS
+        .lock()
+        .values()
+        .flatten()
+        .any(|lock| lock.owner == RecordOwner::Process(pid))
+}
+
+/// Releases the POSIX record locks of process `pid` on `node`. POSIX requires
+/// this on every close of a descriptor referring to the file.
+pub fn release_process_locks_on(node: NodeKey, pid: Pid) {
+    if release_record_locks(|key, lock| *key == node && lock.owner == RecordOwner::Process(pid)) {
+        LOCK_RELEASED.wake();
+    }
+}
+
+/// Releases all POSIX record locks of process `pid`. Called on exit.
+pub fn release_process_locks(pid: Pid) {
+    if release_record_locks(|_, lock| lock.owner == RecordOwner::Process(pid)) {
+        LOCK_RELEASED.wake();
+    }
+}
@@ -0,0 +1,247 @@
+//! Anonymous memory files created by `memfd_create(2)`.
+
+use alloc::{
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use axerrno::{AxError, AxResult};
+use axfs::{FS_CONTEXT, OpenOptions};
+use axhal::paging::MappingFlags;
+use axmm::{AddrSpace, backend::Backend};
+use axsync::Mutex;
+use linux_raw_sys::general::{
+    F_SEAL_FUTURE_WRITE, F_SEAL_GROW, F_SEAL_SEAL, F_SEAL_SHRINK, F_SEAL_WRITE,
+};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr};
+
+use super::File;
+
+const ALL_SEALS: u32 =
+    F_SEAL_SEAL | F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_WRITE | F_SEAL_FUTURE_WRITE;
+
+/// The `memfd`-specific state of a [`File`].
+pub struct Memfd {
+    name: String,
+    /// Shared with the entries of [`SHARED_MAPS`], which also identify the
+    /// file by it.
+    seals: Arc<AtomicU32>,
+}
+
+/// A shared mapping of a memfd over `[start, end)` in an address space.
+struct SharedMap {
+    seals: Arc<AtomicU32>,
+    aspace: Weak<Mutex<AddrSpace>>,
+    start: VirtAddr,
+    end: VirtAddr,
+}
+
+impl SharedMap {
+    fn is_in(&self, aspace: &Arc<Mutex<AddrSpace>>) -> bool {
+        self.aspace.as_ptr() == Arc::as_ptr(aspace)
+    }
+
+    fn is_write_sealed(&self) -> bool {
+        self.seals.load(Ordering::Acquire) & (F_SEAL_WRITE | F_SEAL_FUTURE_WRITE) != 0
+    }
+}
+
+/// Shared mappings of all memfds. `munmap`, `execve` and `fork` keep this in
+/// step with the address spaces, and entries of dropped address spaces are
+/// pruned whenever a mapping is added.
+static SHARED_MAPS: Mutex<Vec<SharedMap>> = Mutex::new(Vec::new());
+
+impl Memfd {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn seals(&self) -> u32 {
+        self.seals.load(Ordering::Acquire)
+    }
+
+    /// Adds `seals`, as `fcntl(F_ADD_SEALS)` does.
+    pub fn add_seals(&self, seals: u32) -> AxResult<()> {
+        if seals & !ALL_SEALS != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        if self.seals() & F_SEAL_SEAL != 0 {
+            return Err(AxError::OperationNotPermitted);
+        }
+        if seals & F_SEAL_WRITE != 0 && self.has_write_maps() {
+            return Err(AxError::ResourceBusy);
+        }
+        self.seals.fetch_or(seals, Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Checks a write ending at `end` into a file of `size` bytes.
+    pub fn check_write(&self, size: u64, end: u64) -> AxResult<()> {
+        let seals = self.seals();
+        if seals & (F_SEAL_WRITE | F_SEAL_FUTURE_WRITE) != 0
+            || (end > size && seals & F_SEAL_GROW != 0)
+        {
+            return Err(AxError::OperationNotPermitted);
+        }
+        Ok(())
+    }
+
+    /// Checks resizing a file of `size` bytes to `new_size` bytes.
+    pub fn check_resize(&self, size: u64, new_size: u64) -> AxResult<()> {
+        let seals = self.seals();
+        if (new_size < size && seals & F_SEAL_SHRINK != 0)
+            || (new_size > size && seals & F_SEAL_GROW != 0)
+        {
+            return Err(AxError::OperationNotPermitted);
+        }
+        Ok(())
+    }
+
+    /// Records a shared mapping of the file over `[start, start + len)` in
+    /// `aspace`, writable or not, since `mprotect` may make it writable later.
+    pub fn add_shared_map(&self, aspace: &Arc<Mutex<AddrSpace>>, start: VirtAddr, len: usize) {
+        let mut maps = SHARED_MAPS.lock();
+        maps.retain(|map| map.aspace.strong_count() > 0);
+        maps.push(SharedMap {
+            seals: self.seals.clone(),
+            aspace: Arc::downgrade(aspace),
+            start,
+            end: start + len,
+        });
+    }
+
+    fn has_write_maps(&self) -> bool {
+        // Not holding `SHARED_MAPS` while locking an address space, since
+        // `mmap` records mappings with its address space locked.
+        let maps: Vec<_> = SHARED_MAPS
+            .lock()
+            .iter()
+            .filter(|map| Arc::ptr_eq(&map.seals, &self.seals))
+            .map(|map| (map.aspace.clone(), map.start, map.end))
+            .collect();
+        maps.into_iter().any(|(aspace, start, end)| {
+            aspace
+                .upgrade()
+                .is_some_and(|aspace| has_writable_area(&aspace.lock(), start, end))
+        })
+    }
+}
+
+/// Whether a writable shared file mapping overlaps `[start, end)`.
+fn has_writable_area(aspace: &AddrSpace, start: VirtAddr, end: VirtAddr) -> bool {
+    let mut addr = start;
+    while addr < end {
+        match aspace.find_area(addr) {
+            Some(area) => {
+                if area.flags().contains(MappingFlags::WRITE)
+                    && matches!(area.backend(), Backend::File(_))
+                {
+                    return true;
+                }
+                addr = area.end();
+            }
+            None => addr += PAGE_SIZE_4K,
+        }
+    }
+    false
+}
+
+/// Forgets the memfd mappings of `aspace` within `[start, start + len)`, once
+/// the range has been unmapped.
+pub fn forget_shared_maps(aspace: &Arc<Mutex<AddrSpace>>, start: VirtAddr, len: usize) {
+    let end = start + len;
+    let mut maps = SHARED_MAPS.lock();
+    let mut rest = Vec::new();
+    maps.retain(|map| {
+        if !map.is_in(aspace) || map.end <= start || end <= map.start {
+            return true;
+        }
+        if map.start < start {
+            rest.push(SharedMap {
+                seals: map.seals.clone(),
+                aspace: map.aspace.clone(),
+                start: map.start,
+                end: start,
+            });
+        }
+        if end < map.end {
+            rest.push(SharedMap {
+                seals: map.seals.clone(),
+                aspace: map.aspace.clone(),
+                start: end,
+                end: map.end,
+            });
+        }
+        false
+    });
+    maps.extend(rest);
+}
+
+/// Forgets all memfd mappings of `aspace`, once it has been cleared by
+/// `execve`.
+pub fn forget_all_shared_maps(aspace: &Arc<Mutex<AddrSpace>>) {
+    SHARED_MAPS.lock().retain(|map| !map.is_in(aspace));
+}
+
+/// Gives the forked address space `child` the memfd mappings of `parent`.
+pub fn fork_shared_maps(parent: &Arc<Mutex<AddrSpace>>, child: &Arc<Mutex<AddrSpace>>) {
+    let mut maps = SHARED_MAPS.lock();
+    let inherited: Vec<_> = maps
+        .iter()
+        .filter(|map| map.is_in(parent))
+        .map(|map| SharedMap {
+            seals: map.seals.clone(),
+            aspace: Arc::downgrade(child),
+            start: map.start,
+            end: map.end,
+        })
+        .collect();
+    maps.extend(inherited);
+}
+
+/// Checks making `[start, start + len)` of `aspace` writable: shared mappings
+/// of write-sealed memfds may not become writable.
+pub fn check_protect(aspace: &Arc<Mutex<AddrSpace>>, start: VirtAddr, len: usize) -> AxResult<()> {
+    let end = start + len;
+    let sealed = SHARED_MAPS.lock().iter().any(|map| {
+        map.is_in(aspace) && map.start < end && start < map.end && map.is_write_sealed()
+    });
+    if sealed {
+        return Err(AxError::PermissionDenied);
+    }
+    Ok(())
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Creates an anonymous memory file named `name`.
+///
+/// The file is backed by the memory filesystem mounted at `/dev/shm`, but is
+/// unlinked right away: it is only reachable through its descriptors and its
+/// pages are freed with the last of them. Since it is an ordinary [`File`],
+/// `read`, `write`, `ftruncate`, `fallocate` and `mmap` all work on it.
+///
+/// Without `allow_sealing` the file starts out with `F_SEAL_SEAL`, so no seals
+/// can ever be added.
+pub fn create_memfd(name: String, allow_sealing: bool) -> AxResult<File> {
+    let fs = FS_CONTEXT.lock().clone();
+    let path = format!("/dev/shm/.memfd-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&fs, &path)?
+        .into_file()?;
+    fs.remove_file(&path)?;
+    let seals = if allow_sealing { 0 } else { F_SEAL_SEAL };
+    Ok(File::new_memfd(
+        file,
+        Memfd {
+            name,
+            seals: Arc::new(AtomicU32::new(seals)),
+        },
+    ))
+}
@@ -1,29 +1,42 @@
+pub mod checkpoint;
 pub mod epoll;
 pub mod event;
 mod fs;
+pub mod inotify;
+pub mod ioring;
+pub mod lock;
+pub mod memfd;
 mod net;
+mod owner;
 mod pidfd;
 mod pipe;
 pub mod signalfd;
+pub mod timerfd;
 
-use alloc::{borrow::Cow, sync::Arc};
-use core::{ffi::c_int, time::Duration};
+use alloc::{borrow::Cow, sync::Arc, vec::Vec};
+use core::{ffi::c_int, task::Waker, time::Duration};
 
 use axerrno::{AxError, AxResult};
 use axfs::{FS_CONTEXT, OpenOptions};
 use axfs_ng_vfs::DeviceId;
 use axio::prelude::*;
-use axpoll::Pollable;
+use axpoll::{PollSet, Pollable};
 use axtask::current;
 use downcast_rs::{DowncastSync, impl_downcast};
 use flatten_objects::FlattenObjects;
-use linux_raw_sys::general::{RLIMIT_NOFILE, stat, statx, statx_timestamp};
+use linux_raw_sys::general::{
+    RLIMIT_NOFILE, __kernel_fsid_t, stat, statfs, statx, statx_timestamp,
+};
 use spin::RwLock;
 use starry_core::{resources::AX_FILE_LIMIT, task::AsThread};
 
 pub use self::{
-    fs::{Directory, File, ResolveAtResult, metadata_to_kstat, resolve_at, with_fs},
+    fs::{
+        Directory, File, ResolveAtResult, forget_dir_nlinks, location_to_kstat,
+        location_to_statfs, metadata_to_kstat, resolve_at, resolve_follow, with_fs,
+    },
     net::Socket,
+    owner::FileOwner,
     pidfd::PidFd,
     pipe::Pipe,
 };
@@ -91,6 +104,40 @@ impl From<Kstat> for stat {
     }
 }
 
+/// 32-bit ABIs keep `stat` with narrow fields and report 64-bit sizes and
+/// inode numbers through `stat64`.
+#[cfg(target_pointer_width = "32")]
+impl From<Kstat> for linux_raw_sys::general::stat64 {
+    fn from(value: Kstat) -> Self {
+        // SAFETY: valid for stat64
+        let mut stat: Self = unsafe { core::mem::zeroed() };
+        stat.st_dev = value.dev as _;
+        stat.st_ino = value.ino as _;
+        // The legacy layout also carries a truncated inode number up front
+        #[cfg(any(target_arch = "arm", target_arch = "x86"))]
+        {
+            stat.__st_ino = value.ino as _;
+        }
+        stat.st_nlink = value.nlink as _;
+        stat.st_mode = value.mode as _;
+        stat.st_uid = value.uid as _;
+        stat.st_gid = value.gid as _;
+        stat.st_size = value.size as _;
+        stat.st_blksize = value.blksize as _;
+        stat.st_blocks = value.blocks as _;
+        stat.st_rdev = value.rdev.0 as _;
+
+        stat.st_atime = value.atime.as_secs() as _;
+        stat.st_atime_nsec = value.atime.subsec_nanos() as _;
+        stat.st_mtime = value.mtime.as_secs() as _;
+        stat.st_mtime_nsec = value.mtime.subsec_nanos() as _;
+        stat.st_ctime = value.ctime.as_secs() as _;
+        stat.st_ctime_nsec = value.ctime.subsec_nanos() as _;
+
+        stat
+    }
+}
+
 impl From<Kstat> for statx {
     fn from(value: Kstat) -> Self {
         // SAFETY: valid for statx
@@ -125,6 +172,43 @@ impl From<Kstat> for statx {
     }
 }
 
+/// Filesystem statistics, as reported by `statfs`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Statfs {
+    pub fs_type: u64,
+    pub block_size: u64,
+    pub blocks: u64,
+    pub blocks_free: u64,
+    pub blocks_available: u64,
+    pub files: u64,
+    pub files_free: u64,
+    pub fsid: u64,
+    pub name_length: u64,
+    pub fragment_size: u64,
+    pub flags: u64,
+}
+
+impl From<Statfs> for statfs {
+    fn from(value: Statfs) -> Self {
+        // SAFETY: valid for statfs
+        let mut statfs: statfs = unsafe { core::mem::zeroed() };
+        statfs.f_type = value.fs_type as _;
+        statfs.f_bsize = value.block_size as _;
+        statfs.f_blocks = value.blocks as _;
+        statfs.f_bfree = value.blocks_free as _;
+        statfs.f_bavail = value.blocks_available as _;
+        statfs.f_files = value.files as _;
+        statfs.f_ffree = value.files_free as _;
+        statfs.f_fsid = __kernel_fsid_t {
+            val: [value.fsid as _, (value.fsid >> 32) as _],
+        };
+        statfs.f_namelen = value.name_length as _;
+        statfs.f_frsize = value.fragment_size as _;
+        statfs.f_flags = value.flags as _;
+        statfs
+    }
+}
+
 pub trait WriteBuf: Write + IoBufMut {}
 impl<T: Write + IoBufMut> WriteBuf for T {}
 pub type IoDst<'a> = dyn WriteBuf + 'a;
@@ -147,6 +231,11 @@ pub trait FileLike: Pollable + DowncastSync {
         Ok(Kstat::default())
     }
 
+    /// Returns statistics of the filesystem the file lives on.
+    fn statfs(&self) -> AxResult<Statfs> {
+        Err(AxError::OperationNotSupported)
+    }
+
     fn path(&self) -> Cow<'_, str>;
 
     fn ioctl(&self, _cmd: u32, _arg: usize) -> AxResult<usize> {
@@ -161,6 +250,17 @@ pub trait FileLike: Pollable + DowncastSync {
         Ok(())
     }
 
+    /// Returns the `F_SETOWN`/`O_ASYNC` state, if the file supports `SIGIO`.
+    fn owner(&self) -> Option<&FileOwner> {
+        None
+    }
+
+    /// Manipulates the space allocated for `[offset, offset + len)`, as
+    /// `fallocate(2)` does.
+    fn fallocate(&self, _mode: u32, _offset: u64, _len: u64) -> AxResult {
+        Err(AxError::OperationNotSupported)
+    }
+
     fn from_fd(fd: c_int) -> AxResult<Arc<Self>>
     where
         Self: Sized + 'static,
@@ -179,6 +279,30 @@ pub trait FileLike: Pollable + DowncastSync {
 }
 impl_downcast!(sync FileLike);
 
+/// Writes all of `src` to `f`, retrying after short writes.
+///
+/// This is only meant for byte streams: record-oriented files such as eventfd
+/// consume a single record per write and must not be looped over.
+///
+/// Once some data has been written, a later error (including `EINTR` and
+/// `EAGAIN`) ends the loop and the partial count is returned, as `write(2)`
+/// does; the error will resurface on the next call. Retrying `EINTR` here would
+/// spin, since the signal stays pending until the syscall returns. A write
+/// that makes no progress on a non-empty buffer fails with `EPIPE`.
+pub fn write_all(f: &dyn FileLike, src: &mut IoSrc) -> AxResult<usize> {
+    let mut total = 0;
+    while src.remaining() > 0 {
+        match f.write(src) {
+            Ok(0) if total == 0 => return Err(AxError::BrokenPipe),
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(total)
+}
+
 #[derive(Clone)]
 pub struct FileDescriptor {
     pub inner: Arc<dyn FileLike>,
@@ -188,6 +312,16 @@ pub struct FileDescriptor {
 scope_local::scope_local! {
     /// The current file descriptor table.
     pub static FD_TABLE: Arc<RwLock<FlattenObjects<FileDescriptor, AX_FILE_LIMIT>>> = Arc::default();
+    /// Woken whenever a descriptor of [`FD_TABLE`] is closed. Shared along
+    /// with the table.
+    pub static FD_CLOSED: Arc<PollSet> = Arc::default();
+}
+
+/// Registers `waker` to be woken by the next close of a file descriptor in
+/// the current table, so that a blocked `poll` can notice that one of its fds
+/// went away.
+pub fn register_close_waker(waker: &Waker) {
+    FD_CLOSED.register(waker);
 }
 
 /// Get a file-like object by `fd`.
@@ -199,56 +333,132 @@ pub fn get_file_like(fd: c_int) -> AxResult<Arc<dyn FileLike>> {
         .ok_or(AxError::BadFileDescriptor)
 }
 
-/// Add a file to the file descriptor table.
+/// Copies the current file descriptor table for a child that does not share
+/// it (no `CLONE_FILES`).
+///
+/// The open file descriptions are shared with the parent, while the
+/// descriptor slots and `cloexec` flags can diverge afterwards.
+pub fn clone_fd_table() -> FlattenObjects<FileDescriptor, AX_FILE_LIMIT> {
+    FD_TABLE.read().clone()
+}
+
+/// Returns whether `fd` is below the soft `RLIMIT_NOFILE` of the current
+/// process.
+///
+/// The limit only bounds new descriptors: lowering it below an already open
+/// descriptor leaves that descriptor usable. `RLIM_INFINITY` never rejects.
+pub fn fd_within_limit(fd: usize) -> bool {
+    (fd as u64) < current().as_thread().proc_data.rlim.read()[RLIMIT_NOFILE].current
+}
+
+/// Add a file to the file descriptor table at the lowest free fd.
 pub fn add_file_like(f: Arc<dyn FileLike>, cloexec: bool) -> AxResult<c_int> {
-    let max_nofile = current().as_thread().proc_data.rlim.read()[RLIMIT_NOFILE].current;
     let mut table = FD_TABLE.write();
-    if table.count() as u64 >= max_nofile {
-        return Err(AxError::TooManyOpenFiles);
+    // POSIX requires the lowest free descriptor, and `RLIMIT_NOFILE` bounds the
+    // descriptor number rather than the number of open files.
+    let fd = (0..AX_FILE_LIMIT)
+        .find(|&fd| !table.is_assigned(fd))
+        .filter(|&fd| fd_within_limit(fd))
+        .ok_or(AxError::TooManyOpenFiles)?;
+    table
+        .add_at(fd, FileDescriptor { inner: f, cloexec })
+        .map_err(|_| AxError::TooManyOpenFiles)?;
+    Ok(fd as c_int)
+}
+
+/// Descriptors removed from the file descriptor table, closed for good when
+/// this is dropped.
+///
+/// Closing is deferred so that it happens after the table lock is released:
+/// dropping the last reference to a file can block or wake other tasks, and so
+/// can releasing its record locks. Declare it before taking the table guard,
+/// so that it is dropped after it.
+///
+/// Every path that closes a descriptor goes through here: `close`, `dup2`
+/// over an open descriptor, `close_range` and `O_CLOEXEC` on `execve`.
+#[derive(Default)]
+pub struct ClosedFds(Vec<FileDescriptor>);
+
+impl ClosedFds {
+    /// Removes `fd` from `fd_table`. Returns `false` if `fd` was not open.
+    pub fn take(
+        &mut self,
+        fd_table: &mut FlattenObjects<FileDescriptor, AX_FILE_LIMIT>,
+        fd: usize,
+    ) -> bool {
+        let Some(f) = fd_table.remove(fd) else {
+            return false;
+        };
+        debug!("close fd: {fd}, count: {}", Arc::strong_count(&f.inner));
+        self.0.push(f);
+        true
+    }
+}
+
+impl Drop for ClosedFds {
+    fn drop(&mut self) {
+        if self.0.is_empty() {
+            return;
+        }
+        // POSIX record locks of the process go away on any close of the file.
+        let pid = current().as_thread().proc_data.proc.pid();
+        for f in self.0.drain(..) {
+            if lock::holds_process_locks(pid)
+                && let Some(file) = f.inner.downcast_ref::<File>()
+                && let Ok(stat) = file.stat()
+            {
+                lock::release_process_locks_on((stat.dev, stat.ino), pid);
+            }
+        }
+        FD_CLOSED.wake();
     }
-    let fd = FileDescriptor { inner: f, cloexec };
-    Ok(table.add(fd).map_err(|_| AxError::TooManyOpenFiles)? as c_int)
 }
 
 /// Close a file by `fd`.
 pub fn close_file_like(fd: c_int) -> AxResult {
-    let f = FD_TABLE
-        .write()
-        .remove(fd as usize)
-        .ok_or(AxError::BadFileDescriptor)?;
-    debug!("close_file_like <= count: {}", Arc::strong_count(&f.inner));
-    Ok(())
+    let mut closed = ClosedFds::default();
+    if closed.take(&mut FD_TABLE.write(), fd as usize) {
+        Ok(())
+    } else {
+        Err(AxError::BadFileDescriptor)
+    }
 }
 
+/// Opens `/dev/console` as stdin, stdout and stderr of an empty fd table.
 pub fn add_stdio(fd_table: &mut FlattenObjects<FileDescriptor, AX_FILE_LIMIT>) -> AxResult<()> {
+    add_stdio_with(fd_table, "/dev/console", "/dev/console", "/dev/console")
+}
+
+/// Opens the given devices as stdin, stdout and stderr of an empty fd table.
+///
+/// stdout and stderr share one open file when they name the same path.
+pub fn add_stdio_with(
+    fd_table: &mut FlattenObjects<FileDescriptor, AX_FILE_LIMIT>,
+    path_in: &str,
+    path_out: &str,
+    path_err: &str,
+) -> AxResult<()> {
     assert_eq!(fd_table.count(), 0);
     let cx = FS_CONTEXT.lock();
-    let open = |options: &mut OpenOptions| {
-        AxResult::Ok(Arc::new(File::new(
-            options.open(&cx, "/dev/console")?.into_file()?,
-        )))
+    let open = |path: &str, options: &mut OpenOptions| {
+        AxResult::Ok(Arc::new(File::new(options.open(&cx, path)?.into_file()?)))
     };
 
-    let tty_in = open(OpenOptions::new().read(true).write(false))?;
-    let tty_out = open(OpenOptions::new().read(false).write(true))?;
-    fd_table
-        .add(FileDescriptor {
-            inner: tty_in,
-            cloexec: false,
-        })
-        .map_err(|_| AxError::TooManyOpenFiles)?;
-    fd_table
-        .add(FileDescriptor {
-            inner: tty_out.clone(),
-            cloexec: false,
-        })
-        .map_err(|_| AxError::TooManyOpenFiles)?;
-    fd_table
-        .add(FileDescriptor {
-            inner: tty_out,
-            cloexec: false,
-        })
-        .map_err(|_| AxError::TooManyOpenFiles)?;
+    let tty_in = open(path_in, OpenOptions::new().read(true).write(false))?;
+    let tty_out = open(path_out, OpenOptions::new().read(false).write(true))?;
+    let tty_err = if path_err == path_out {
+        tty_out.clone()
+    } else {
+        open(path_err, OpenOptions::new().read(false).write(true))?
+    };
+    for inner in [tty_in, tty_out, tty_err] {
+        fd_table
+            .add(FileDescriptor {
+                inner,
+                cloexec: false,
+            })
+            .map_err(|_| AxError::TooManyOpenFiles)?;
+    }
 
     Ok(())
 }
@@ -1,34 +1,115 @@
 use alloc::{borrow::Cow, format, sync::Arc};
-use core::{ffi::c_int, ops::Deref, task::Context};
+use core::{
+    ffi::c_int,
+    ops::Deref,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Context,
+};
 
-use axerrno::{AxError, AxResult};
+use axerrno::{AxError, AxResult, LinuxError};
 use axnet::{
-    SocketOps,
+    Shutdown, SocketAddrEx, SocketOps,
     options::{Configurable, GetSocketOption, SetSocketOption},
 };
 use axpoll::{IoEvents, Pollable};
 use linux_raw_sys::general::S_IFSOCK;
 
-use super::{FileLike, Kstat};
-use crate::file::{IoDst, IoSrc, get_file_like};
+use super::{FileLike, FileOwner, Kstat};
+use crate::{
+    file::{IoDst, IoSrc, get_file_like},
+    signal::raise_sigpipe,
+};
 
-pub struct Socket(pub axnet::Socket);
+pub struct Socket {
+    inner: axnet::Socket,
+    owner: FileOwner,
+    /// Whether this is a `SOCK_STREAM` socket, which has no message boundaries.
+    stream: bool,
+    /// Whether a non-blocking connect is still in progress.
+    connecting: AtomicBool,
+    /// Whether the read side has reached end of file or has been shut down.
+    read_closed: AtomicBool,
+}
+
+impl Socket {
+    pub fn new(inner: axnet::Socket, stream: bool) -> Self {
+        Self {
+            inner,
+            owner: FileOwner::default(),
+            stream,
+            connecting: AtomicBool::new(false),
+            read_closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns whether this is a byte stream socket.
+    pub fn is_stream(&self) -> bool {
+        self.stream
+    }
+
+    /// Connects the socket.
+    ///
+    /// A non-blocking connect that cannot complete immediately returns
+    /// [`AxError::WouldBlock`]. The socket then becomes writable once the
+    /// connection is established or has failed, and calling this again before
+    /// that returns `EALREADY`.
+    pub fn connect(&self, addr: SocketAddrEx) -> AxResult {
+        if self.connecting.load(Ordering::Acquire) {
+            if !self.poll().contains(IoEvents::OUT) {
+                return Err(AxError::from(LinuxError::EALREADY));
+            }
+            self.connecting.store(false, Ordering::Release);
+        }
+        self.inner.connect(addr).inspect_err(|err| {
+            if matches!(err, AxError::WouldBlock) {
+                self.connecting.store(true, Ordering::Release);
+            }
+        })
+    }
+
+    /// Notes that a read of `len` bytes returned `read`. A stream socket only
+    /// reads nothing at end of file.
+    pub fn note_read(&self, len: usize, read: usize) {
+        if self.stream && len > 0 && read == 0 {
+            self.read_closed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Shuts down the read or write side of the socket, or both.
+    pub fn shutdown(&self, how: Shutdown) -> AxResult {
+        let read = matches!(how, Shutdown::Read | Shutdown::Both);
+        self.inner.shutdown(how)?;
+        if read {
+            self.read_closed.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+}
 
 impl Deref for Socket {
     type Target = axnet::Socket;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.inner
     }
 }
 
 impl FileLike for Socket {
     fn read(&self, dst: &mut IoDst) -> AxResult<usize> {
-        self.recv(dst, axnet::RecvOptions::default())
+        let len = dst.remaining_mut();
+        let read = self.recv(dst, axnet::RecvOptions::default())?;
+        self.note_read(len, read);
+        self.owner.arm(self);
+        Ok(read)
     }
 
     fn write(&self, src: &mut IoSrc) -> AxResult<usize> {
         self.send(src, axnet::SendOptions::default())
+            .inspect_err(|err| {
+                if matches!(err, AxError::BrokenPipe) {
+                    raise_sigpipe();
+                }
+            })
     }
 
     fn stat(&self) -> AxResult<Kstat> {
@@ -48,10 +129,14 @@ impl FileLike for Socket {
     }
 
     fn set_nonblocking(&self, nonblocking: bool) -> AxResult<()> {
-        self.0
+        self.inner
             .set_option(SetSocketOption::NonBlocking(&nonblocking))
     }
 
+    fn owner(&self) -> Option<&FileOwner> {
+        Some(&self.owner)
+    }
+
     fn path(&self) -> Cow<'_, str> {
         format!("socket:[{}]", self as *const _ as usize).into()
     }
@@ -67,10 +152,16 @@ impl FileLike for Socket {
 }
 impl Pollable for Socket {
     fn poll(&self) -> IoEvents {
-        self.0.poll()
+        let mut events = self.inner.poll();
+        // A hangup also shuts down the read side, which `EPOLLRDHUP` watchers
+        // expect to see on its own.
+        if events.contains(IoEvents::HUP) || self.read_closed.load(Ordering::Acquire) {
+            events |= IoEvents::RDHUP;
+        }
+        events
     }
 
     fn register(&self, context: &mut Context<'_>, events: IoEvents) {
-        self.0.register(context, events);
+        self.inner.register(context, events);
     }
 }
@@ -0,0 +1,89 @@
+use alloc::{
+    sync::{Arc, Weak},
+    task::Wake,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+    task::{Context, Waker},
+};
+
+use axpoll::{IoEvents, Pollable};
+use starry_core::task::{send_signal_to_process, send_signal_to_process_group};
+use starry_process::Pid;
+use starry_signal::{SignalInfo, Signo};
+
+#[derive(Default)]
+struct OwnerInner {
+    /// A positive value is a pid, a negative value is a process group id and
+    /// zero means no owner.
+    owner: AtomicI32,
+    async_io: AtomicBool,
+    armed: AtomicBool,
+}
+
+impl OwnerInner {
+    fn send_sigio(&self) {
+        let sig = Some(SignalInfo::new_kernel(Signo::SIGIO));
+        let _ = match self.owner.load(Ordering::Acquire) {
+            0 => return,
+            pid @ 1.. => send_signal_to_process(pid as Pid, sig),
+            pgid => send_signal_to_process_group(pgid.unsigned_abs() as Pid, sig),
+        };
+    }
+}
+
+struct SigioWaker(Weak<OwnerInner>);
+
+impl Wake for SigioWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let Some(inner) = self.0.upgrade() else {
+            return;
+        };
+        inner.armed.store(false, Ordering::Release);
+        if inner.async_io.load(Ordering::Acquire) {
+            inner.send_sigio();
+        }
+    }
+}
+
+/// The `F_SETOWN`/`O_ASYNC` state of an open file.
+///
+/// When asynchronous I/O is enabled and an owner is set, [`FileOwner::arm`]
+/// registers a one-shot waker on the file that sends `SIGIO` to the owner
+/// once the file becomes readable. Files re-arm it after consuming data.
+#[derive(Default)]
+pub struct FileOwner(Arc<OwnerInner>);
+
+impl FileOwner {
+    pub fn owner(&self) -> i32 {
+        self.0.owner.load(Ordering::Acquire)
+    }
+
+    pub fn set_owner(&self, owner: i32) {
+        self.0.owner.store(owner, Ordering::Release);
+    }
+
+    pub fn is_async(&self) -> bool {
+        self.0.async_io.load(Ordering::Acquire)
+    }
+
+    pub fn set_async(&self, enabled: bool) {
+        self.0.async_io.store(enabled, Ordering::Release);
+    }
+
+    /// Arms `SIGIO` delivery for the next time `file` becomes readable.
+    pub fn arm(&self, file: &(impl Pollable + ?Sized)) {
+        if !self.is_async() || self.owner() == 0 {
+            return;
+        }
+        if self.0.armed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let waker = Waker::from(Arc::new(SigioWaker(Arc::downgrade(&self.0))));
+        file.register(&mut Context::from_waker(&waker), IoEvents::IN);
+    }
+}
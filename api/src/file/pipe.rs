@@ -1,4 +1,4 @@
-use alloc::{borrow::Cow, format, sync::Arc};
+use alloc::{borrow::Cow, format, sync::Arc, vec, vec::Vec};
 use core::{
     mem,
     sync::atomic::{AtomicBool, Ordering},
@@ -7,28 +7,34 @@ use core::{
 
 use axerrno::{AxError, AxResult};
 use axpoll::{IoEvents, PollSet, Pollable};
-use axsync::Mutex;
-use axtask::{
-    current,
-    future::{block_on, poll_io},
-};
+use axsync::{Mutex, MutexGuard};
+use axtask::future::{block_on, poll_io};
 use linux_raw_sys::{general::S_IFIFO, ioctl::FIONREAD};
 use memory_addr::PAGE_SIZE_4K;
 use ringbuf::{
     HeapRb,
     traits::{Consumer, Observer, Producer},
 };
-use starry_core::task::{AsThread, send_signal_to_process};
-use starry_signal::{SignalInfo, Signo};
 use starry_vm::VmMutPtr;
 
-use super::{FileLike, Kstat};
-use crate::file::{IoDst, IoSrc};
+use super::{FileLike, FileOwner, Kstat};
+use crate::{
+    file::{IoDst, IoSrc},
+    signal::raise_sigpipe,
+};
 
 const RING_BUFFER_INIT_SIZE: usize = 65536; // 64 KiB
 
+/// The most bytes a splice copies through a temporary buffer at once.
+const SPLICE_CHUNK: usize = 16 * PAGE_SIZE_4K;
+
 struct Shared {
     buffer: Mutex<HeapRb<u8>>,
+    /// Serializes consumers, so that a splice can peek at the buffer, do the
+    /// I/O of the other end without holding `buffer` and consume afterwards.
+    reader: Mutex<()>,
+    /// Serializes producers, likewise.
+    writer: Mutex<()>,
     poll_rx: PollSet,
     poll_tx: PollSet,
     poll_close: PollSet,
@@ -38,6 +44,7 @@ pub struct Pipe {
     read_side: bool,
     shared: Arc<Shared>,
     non_blocking: AtomicBool,
+    owner: FileOwner,
 }
 impl Drop for Pipe {
     fn drop(&mut self) {
@@ -49,6 +56,8 @@ impl Pipe {
     pub fn new() -> (Pipe, Pipe) {
         let shared = Arc::new(Shared {
             buffer: Mutex::new(HeapRb::new(RING_BUFFER_INIT_SIZE)),
+            reader: Mutex::new(()),
+            writer: Mutex::new(()),
             poll_rx: PollSet::new(),
             poll_tx: PollSet::new(),
             poll_close: PollSet::new(),
@@ -57,11 +66,13 @@ impl Pipe {
             read_side: true,
             shared: shared.clone(),
             non_blocking: AtomicBool::new(false),
+            owner: FileOwner::default(),
         };
         let write_end = Pipe {
             read_side: false,
             shared,
             non_blocking: AtomicBool::new(false),
+            owner: FileOwner::default(),
         };
         (read_end, write_end)
     }
@@ -85,6 +96,8 @@ impl Pipe {
     pub fn resize(&self, new_size: usize) -> AxResult<()> {
         let new_size = new_size.div_ceil(PAGE_SIZE_4K).max(1) * PAGE_SIZE_4K;
 
+        // A splice in progress may have measured the free space already.
+        let _writer = self.shared.writer.lock();
         let mut buffer = self.shared.buffer.lock();
         if new_size == buffer.capacity().get() {
             return Ok(());
@@ -98,15 +111,211 @@ impl Pipe {
         buffer.push_slice(right);
         Ok(())
     }
+
+    /// Returns an id shared by both ends of the same pipe.
+    pub(super) fn pair_id(&self) -> usize {
+        Arc::as_ptr(&self.shared) as usize
+    }
+
+    /// Copies out the bytes currently buffered, without consuming them.
+    pub(super) fn buffered(&self) -> Vec<u8> {
+        let buffer = self.shared.buffer.lock();
+        let (left, right) = buffer.as_slices();
+        [left, right].concat()
+    }
+
+    /// Creates a pipe with a buffer of `capacity` bytes already holding
+    /// `data`.
+    pub(super) fn with_contents(capacity: usize, data: &[u8]) -> AxResult<(Pipe, Pipe)> {
+        let (read_end, write_end) = Pipe::new();
+        read_end.resize(capacity.max(data.len()))?;
+        read_end.shared.buffer.lock().push_slice(data);
+        Ok((read_end, write_end))
+    }
+
+    /// Hands up to `len` buffered bytes to `f`, consuming as many as `f`
+    /// reports. Blocks until data is available, like [`FileLike::read`].
+    ///
+    /// `f` runs on a copy of the data, without the buffer locked, so it may
+    /// block without stalling the writers of this pipe. A [`AxError::WouldBlock`] from `f` is
+    /// returned as is rather than waited on, since it concerns the other end.
+    pub fn splice_out(
+        &self,
+        len: usize,
+        nonblocking: bool,
+        f: impl FnOnce(&[u8]) -> AxResult<usize>,
+    ) -> AxResult<usize> {
+        if !self.is_read() {
+            return Err(AxError::BadFileDescriptor);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let (_reader, chunk) = loop {
+            block_on(poll_io(self, IoEvents::IN, nonblocking, || {
+                if self.shared.buffer.lock().is_empty() && !self.closed() {
+                    Err(AxError::WouldBlock)
+                } else {
+                    Ok(())
+                }
+            }))?;
+            let reader = self.shared.reader.lock();
+            let chunk = {
+                let buffer = self.shared.buffer.lock();
+                let (left, right) = buffer.as_slices();
+                let len = len.min(SPLICE_CHUNK).min(buffer.occupied_len());
+                let mut chunk = Vec::with_capacity(len);
+                chunk.extend_from_slice(&left[..len.min(left.len())]);
+                chunk.extend_from_slice(&right[..len - chunk.len()]);
+                chunk
+            };
+            if !chunk.is_empty() {
+                break (reader, chunk);
+            }
+            if self.closed() {
+                return Ok(0);
+            }
+            // Another reader got there first
+        };
+
+        let read = f(&chunk)?.min(chunk.len());
+        if read > 0 {
+            unsafe { self.shared.buffer.lock().advance_read_index(read) };
+            self.shared.poll_tx.wake();
+            self.owner.arm(self);
+        }
+        Ok(read)
+    }
+
+    /// Lets `f` fill up to `len` bytes, which are then appended to the
+    /// buffer. Blocks until there is room, like [`FileLike::write`], but
+    /// performs a single transfer. Returns `Ok(0)` once `f` reports end of
+    /// input.
+    ///
+    /// As with [`Pipe::splice_out`], `f` runs without the buffer locked and a
+    /// [`AxError::WouldBlock`] from it is returned as is.
+    pub fn splice_in(
+        &self,
+        len: usize,
+        nonblocking: bool,
+        f: impl FnOnce(&mut [u8]) -> AxResult<usize>,
+    ) -> AxResult<usize> {
+        if !self.is_write() {
+            return Err(AxError::BadFileDescriptor);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let (_writer, room) = loop {
+            block_on(poll_io(self, IoEvents::OUT, nonblocking, || {
+                if self.closed() {
+                    raise_sigpipe();
+                    return Err(AxError::BrokenPipe);
+                }
+                if self.shared.buffer.lock().is_full() {
+                    Err(AxError::WouldBlock)
+                } else {
+                    Ok(())
+                }
+            }))?;
+            let writer = self.shared.writer.lock();
+            let room = self.shared.buffer.lock().vacant_len();
+            if room > 0 {
+                break (writer, room);
+            }
+            // Another writer got there first
+        };
+
+        let mut chunk = vec![0; len.min(SPLICE_CHUNK).min(room)];
+        let written = f(&mut chunk)?.min(chunk.len());
+        if written > 0 {
+            self.shared.buffer.lock().push_slice(&chunk[..written]);
+            self.shared.poll_rx.wake();
+        }
+        Ok(written)
+    }
+
+    /// Moves up to `len` bytes from this pipe's buffer straight into the
+    /// buffer of `dst`, without going through an intermediate copy.
+    pub fn splice_to(&self, dst: &Pipe, len: usize, nonblocking: bool) -> AxResult<usize> {
+        if !self.is_read() || !dst.is_write() {
+            return Err(AxError::BadFileDescriptor);
+        }
+        if Arc::ptr_eq(&self.shared, &dst.shared) {
+            return Err(AxError::InvalidInput);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        loop {
+            block_on(poll_io(dst, IoEvents::OUT, nonblocking, || {
+                if dst.closed() {
+                    raise_sigpipe();
+                    return Err(AxError::BrokenPipe);
+                }
+                if dst.shared.buffer.lock().is_full() {
+                    Err(AxError::WouldBlock)
+                } else {
+                    Ok(())
+                }
+            }))?;
+
+            let moved = block_on(poll_io(self, IoEvents::IN, nonblocking, || {
+                let moved = {
+                    let _reader = self.shared.reader.lock();
+                    let _writer = dst.shared.writer.lock();
+                    let (src_buf, mut dst_buf) = lock_pair(&self.shared, &dst.shared);
+                    if dst_buf.is_full() {
+                        // Raced with another writer, wait for room again.
+                        return Ok(None);
+                    }
+                    let (left, right) = src_buf.as_slices();
+                    let mut count = 0;
+                    for chunk in [left, right] {
+                        let chunk = &chunk[..chunk.len().min(len - count)];
+                        let n = dst_buf.push_slice(chunk);
+                        count += n;
+                        if n < chunk.len() {
+                            break;
+                        }
+                    }
+                    unsafe { src_buf.advance_read_index(count) };
+                    count
+                };
+                if moved > 0 {
+                    self.shared.poll_tx.wake();
+                    dst.shared.poll_rx.wake();
+                    self.owner.arm(self);
+                    Ok(Some(moved))
+                } else if self.closed() {
+                    Ok(Some(0))
+                } else {
+                    Err(AxError::WouldBlock)
+                }
+            }))?;
+            if let Some(moved) = moved {
+                return Ok(moved);
+            }
+        }
+    }
 }
 
-fn raise_pipe() {
-    let curr = current();
-    send_signal_to_process(
-        curr.as_thread().proc_data.proc.pid(),
-        Some(SignalInfo::new_kernel(Signo::SIGPIPE)),
-    )
-    .expect("Failed to send SIGPIPE");
+/// Locks the buffers of two pipes in address order so that concurrent
+/// transfers in opposite directions cannot deadlock.
+fn lock_pair<'a>(
+    src: &'a Shared,
+    dst: &'a Shared,
+) -> (MutexGuard<'a, HeapRb<u8>>, MutexGuard<'a, HeapRb<u8>>) {
+    if (src as *const Shared) < (dst as *const Shared) {
+        let src = src.buffer.lock();
+        (src, dst.buffer.lock())
+    } else {
+        let dst = dst.buffer.lock();
+        (src.buffer.lock(), dst)
+    }
 }
 
 impl FileLike for Pipe {
@@ -120,6 +329,7 @@ impl FileLike for Pipe {
 
         block_on(poll_io(self, IoEvents::IN, self.nonblocking(), || {
             let read = {
+                let _reader = self.shared.reader.lock();
                 let cons = self.shared.buffer.lock();
                 let (left, right) = cons.as_slices();
                 let mut count = dst.write(left)?;
@@ -131,6 +341,7 @@ impl FileLike for Pipe {
             };
             if read > 0 {
                 self.shared.poll_tx.wake();
+                self.owner.arm(self);
                 Ok(read)
             } else if self.closed() {
                 Ok(0)
@@ -153,11 +364,12 @@ impl FileLike for Pipe {
 
         block_on(poll_io(self, IoEvents::OUT, self.nonblocking(), || {
             if self.closed() {
-                raise_pipe();
+                raise_sigpipe();
                 return Err(AxError::BrokenPipe);
             }
 
             let written = {
+                let _writer = self.shared.writer.lock();
                 let mut prod = self.shared.buffer.lock();
                 let (left, right) = prod.vacant_slices_mut();
                 let mut count = src.read(unsafe { left.assume_init_mut() })?;
@@ -198,6 +410,10 @@ impl FileLike for Pipe {
         self.non_blocking.load(Ordering::Acquire)
     }
 
+    fn owner(&self) -> Option<&FileOwner> {
+        Some(&self.owner)
+    }
+
     fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
         match cmd {
             FIONREAD => {
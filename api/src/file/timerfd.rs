@@ -0,0 +1,232 @@
+use alloc::{
+    borrow::Cow,
+    sync::{Arc, Weak},
+};
+use core::{
+    future::poll_fn,
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axerrno::{AxError, AxResult};
+use axhal::time::TimeValue;
+use axpoll::{IoEvents, PollSet, Pollable};
+use axsync::Mutex;
+use axtask::future::{block_on, poll_io, timeout};
+
+use crate::file::{FileLike, IoDst, IoSrc};
+
+struct TimerState {
+    /// The next expiration, in the time base of the timer's clock.
+    deadline: Option<TimeValue>,
+    interval: TimeValue,
+    /// Expirations not yet consumed by `read`.
+    ticks: u64,
+    /// Bumped on every `settime` so that the wakeup task notices.
+    generation: u64,
+    /// Whether the wakeup task of the timer is running.
+    waiter: bool,
+}
+
+impl TimerState {
+    /// Accounts for the expirations that happened up to `now`.
+    fn update(&mut self, now: TimeValue) {
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        if now < deadline {
+            return;
+        }
+        if self.interval.is_zero() {
+            self.ticks += 1;
+            self.deadline = None;
+        } else {
+            let interval = self.interval.as_nanos();
+            let n = (now - deadline).as_nanos() / interval + 1;
+            self.ticks += n as u64;
+            self.deadline = Some(deadline + Duration::from_nanos((n * interval) as u64));
+        }
+    }
+}
+
+/// A `timerfd` object.
+///
+/// Expirations are computed lazily from the timer's clock whenever the timer
+/// is inspected. While armed, a single kernel task sleeps until each deadline
+/// to wake up readers and pollers, and starts over whenever `settime` changes
+/// the deadline.
+pub struct TimerFd {
+    clock: fn() -> TimeValue,
+    state: Mutex<TimerState>,
+    non_blocking: AtomicBool,
+    poll_rx: PollSet,
+    /// Woken when the setting changes or the timer goes away.
+    rearm: PollSet,
+}
+
+impl TimerFd {
+    pub fn new(clock: fn() -> TimeValue) -> Arc<Self> {
+        Arc::new(Self {
+            clock,
+            state: Mutex::new(TimerState {
+                deadline: None,
+                interval: Duration::ZERO,
+                ticks: 0,
+                generation: 0,
+                waiter: false,
+            }),
+            non_blocking: AtomicBool::new(false),
+            poll_rx: PollSet::new(),
+            rearm: PollSet::new(),
+        })
+    }
+
+    /// Returns the current `(interval, remaining)` setting of the timer. A zero
+    /// remaining time means the timer is disarmed.
+    pub fn get(&self) -> (TimeValue, TimeValue) {
+        let now = (self.clock)();
+        let mut state = self.state.lock();
+        state.update(now);
+        let remaining = state.deadline.map_or(Duration::ZERO, |it| it - now);
+        (state.interval, remaining)
+    }
+
+    /// Arms (or disarms, if `value` is zero) the timer, returning the previous
+    /// setting. `value` is an absolute time of the timer's clock if `absolute`
+    /// is set, and relative to now otherwise.
+    pub fn set(
+        self: &Arc<Self>,
+        interval: TimeValue,
+        value: TimeValue,
+        absolute: bool,
+    ) -> (TimeValue, TimeValue) {
+        let now = (self.clock)();
+        let mut state = self.state.lock();
+        state.update(now);
+        let old = (
+            state.interval,
+            state.deadline.map_or(Duration::ZERO, |it| it - now),
+        );
+
+        state.generation += 1;
+        state.ticks = 0;
+        state.interval = interval;
+        state.deadline = if value.is_zero() {
+            None
+        } else if absolute {
+            Some(value)
+        } else {
+            Some(now + value)
+        };
+        let spawn = state.deadline.is_some() && !mem::replace(&mut state.waiter, true);
+        drop(state);
+
+        // A sleeping wakeup task has to pick up the new deadline
+        self.rearm.wake();
+        if spawn {
+            let timer = Arc::downgrade(self);
+            axtask::spawn_with_name(move || block_on(wakeup_task(timer)), "timerfd".into());
+        }
+        old
+    }
+
+    /// Returns how long to wait for the next expiration, along with the
+    /// current generation of the setting. Returns `None` once the timer is
+    /// disarmed, retiring the wakeup task.
+    fn next_wait(&self) -> Option<(Duration, u64)> {
+        let now = (self.clock)();
+        let mut state = self.state.lock();
+        state.update(now);
+        match state.deadline {
+            Some(deadline) => Some((deadline - now, state.generation)),
+            None => {
+                state.waiter = false;
+                None
+            }
+        }
+    }
+}
+
+impl Drop for TimerFd {
+    fn drop(&mut self) {
+        self.rearm.wake();
+    }
+}
+
+/// The wakeup task of a timer. It sleeps until the next deadline or until the
+/// setting changes, whichever comes first, and exits once the timer is
+/// disarmed or dropped.
+async fn wakeup_task(timer: Weak<TimerFd>) {
+    while let Some((wait, generation)) = timer.upgrade().and_then(|it| it.next_wait()) {
+        let rearmed = poll_fn(|cx| {
+            let Some(timer) = timer.upgrade() else {
+                return Poll::Ready(());
+            };
+            timer.rearm.register(cx.waker());
+            if timer.state.lock().generation != generation {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        });
+        let _ = timeout(Some(wait), rearmed).await;
+        let Some(timer) = timer.upgrade() else {
+            break;
+        };
+        timer.poll_rx.wake();
+    }
+}
+
+impl FileLike for TimerFd {
+    fn read(&self, dst: &mut IoDst) -> AxResult<usize> {
+        if dst.remaining_mut() < size_of::<u64>() {
+            return Err(AxError::InvalidInput);
+        }
+
+        block_on(poll_io(self, IoEvents::IN, self.nonblocking(), || {
+            let mut state = self.state.lock();
+            state.update((self.clock)());
+            if state.ticks == 0 {
+                return Err(AxError::WouldBlock);
+            }
+            dst.write(&state.ticks.to_ne_bytes())?;
+            state.ticks = 0;
+            Ok(size_of::<u64>())
+        }))
+    }
+
+    fn write(&self, _src: &mut IoSrc) -> AxResult<usize> {
+        Err(AxError::InvalidInput)
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.non_blocking.load(Ordering::Acquire)
+    }
+
+    fn set_nonblocking(&self, non_blocking: bool) -> AxResult {
+        self.non_blocking.store(non_blocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn path(&self) -> Cow<'_, str> {
+        "anon_inode:[timerfd]".into()
+    }
+}
+
+impl Pollable for TimerFd {
+    fn poll(&self) -> IoEvents {
+        let mut state = self.state.lock();
+        state.update((self.clock)());
+        let mut events = IoEvents::empty();
+        events.set(IoEvents::IN, state.ticks > 0);
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        if events.contains(IoEvents::IN) {
+            self.poll_rx.register(context.waker());
+        }
+    }
+}
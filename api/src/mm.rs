@@ -9,10 +9,13 @@ use core::{
 
 use axerrno::{AxError, AxResult};
 use axhal::{
+    mem::phys_to_virt,
     paging::MappingFlags,
     trap::{PAGE_FAULT, register_trap_handler},
 };
 use axio::prelude::*;
+use axmm::{AddrSpace, backend::Backend};
+use axsync::Mutex;
 use axtask::current;
 use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
 use starry_core::{mm::access_user_memory, task::AsThread};
@@ -345,3 +348,115 @@ impl IoBufMut for VmBytesMut {
         self.len
     }
 }
+
+/// Returns the kernel address of the user memory at `vaddr`, which must stay
+/// within one page, after faulting the page in for `access_flags`.
+fn remote_page_ptr(
+    aspace: &mut AddrSpace,
+    vaddr: VirtAddr,
+    len: usize,
+    access_flags: MappingFlags,
+) -> Option<*mut u8> {
+    if !aspace.can_access_range(vaddr, len, access_flags) {
+        return None;
+    }
+    aspace
+        .populate_area(vaddr.align_down_4k(), PAGE_SIZE_4K, access_flags)
+        .ok()?;
+    let (paddr, ..) = aspace.page_table().query(vaddr).ok()?;
+    Some(phys_to_virt(paddr).as_mut_ptr())
+}
+
+/// Copies between `buf` and the memory at `addr` in another address space,
+/// page by page through the kernel mapping of the physical frames.
+///
+/// Stops at the first page that is unmapped or lacks the needed permission,
+/// and returns the number of bytes copied so far.
+fn access_remote(
+    aspace: &Mutex<AddrSpace>,
+    addr: usize,
+    len: usize,
+    access_flags: MappingFlags,
+    mut f: impl FnMut(*mut u8, usize, usize),
+) -> usize {
+    let mut aspace = aspace.lock();
+    let mut done = 0;
+    while done < len {
+        let vaddr = VirtAddr::from(addr.wrapping_add(done));
+        let chunk = (len - done).min(PAGE_SIZE_4K - vaddr.align_offset_4k());
+        let Some(ptr) = remote_page_ptr(&mut aspace, vaddr, chunk, access_flags) else {
+            break;
+        };
+        f(ptr, done, chunk);
+        done += chunk;
+    }
+    done
+}
+
+/// Reads the memory at `addr` in `aspace` into `buf`, returning the number of
+/// bytes read before the first inaccessible page.
+pub fn read_remote(aspace: &Mutex<AddrSpace>, addr: usize, buf: &mut [u8]) -> usize {
+    access_remote(aspace, addr, buf.len(), MappingFlags::READ, |src, off, n| {
+        // SAFETY: `src` maps `n` bytes of a populated user page
+        buf[off..off + n].copy_from_slice(unsafe { slice::from_raw_parts(src, n) });
+    })
+}
+
+/// Writes `buf` to the memory at `addr` in `aspace`, returning the number of
+/// bytes written before the first inaccessible page.
+pub fn write_remote(aspace: &Mutex<AddrSpace>, addr: usize, buf: &[u8]) -> usize {
+    access_remote(aspace, addr, buf.len(), MappingFlags::WRITE, |dst, off, n| {
+        // SAFETY: `dst` maps `n` bytes of a populated, writable user page
+        unsafe { ptr::copy_nonoverlapping(buf[off..].as_ptr(), dst, n) };
+    })
+}
+
+/// Writes `buf` to the memory at `addr` in `aspace` like [`write_remote`], but
+/// also into readable pages without write permission, as a debugger setting a
+/// breakpoint needs (`FOLL_FORCE` in Linux).
+///
+/// Such a page is made writable just for the copy, which gives a private
+/// mapping its own copy of the page first, so the file and the processes
+/// sharing the page are left alone. Shared file mappings are only written
+/// where they are writable anyway.
+pub fn force_write_remote(aspace: &Mutex<AddrSpace>, addr: usize, buf: &[u8]) -> usize {
+    let mut aspace = aspace.lock();
+    let mut done = 0;
+    while done < buf.len() {
+        let vaddr = VirtAddr::from(addr.wrapping_add(done));
+        let chunk = (buf.len() - done).min(PAGE_SIZE_4K - vaddr.align_offset_4k());
+        let Some(area) = aspace.find_area(vaddr) else {
+            break;
+        };
+        let flags = area.flags();
+        let read_only = !flags.contains(MappingFlags::WRITE);
+        if read_only
+            && (!flags.contains(MappingFlags::READ) || matches!(area.backend(), Backend::File(_)))
+        {
+            break;
+        }
+
+        let page = vaddr.align_down_4k();
+        if read_only
+            && aspace
+                .protect(page, PAGE_SIZE_4K, flags | MappingFlags::WRITE)
+                .is_err()
+        {
+            break;
+        }
+        let page_ptr = remote_page_ptr(&mut aspace, vaddr, chunk, MappingFlags::WRITE);
+        if let Some(dst) = page_ptr {
+            // SAFETY: `dst` maps `chunk` bytes of a populated, writable user page
+            unsafe { ptr::copy_nonoverlapping(buf[done..].as_ptr(), dst, chunk) };
+        }
+        if read_only {
+            // The private copy of the page stays, only the permission goes
+            let _ = aspace.protect(page, PAGE_SIZE_4K, flags);
+        }
+        if page_ptr.is_none() {
+            break;
+        }
+        done += chunk;
+    }
+    done
+}
@@ -1,10 +1,22 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
 
-use axerrno::AxResult;
+use axerrno::{AxResult, LinuxError};
 use axhal::uspace::UserContext;
-use axtask::current;
-use starry_core::task::{AsThread, Thread};
-use starry_signal::{SignalOSAction, SignalSet};
+use axtask::{current, future::block_on};
+use linux_raw_sys::general::{CLD_DUMPED, CLD_EXITED, CLD_KILLED};
+use starry_core::task::{
+    AsThread, JobReport, SignalFields, Thread, get_task, kernel_signal_info, notify_job_change,
+    send_signal_to_process,
+};
+use starry_process::Pid;
+use starry_signal::{
+    SignalActionFlags, SignalDisposition, SignalInfo, SignalOSAction, SignalSet, Signo,
+};
+use syscalls::Sysno;
 
 use crate::task::do_exit;
 
@@ -27,11 +39,20 @@ pub fn check_signals(
             do_exit(128 + signo as i32, true);
         }
         SignalOSAction::Stop => {
-            // TODO: implement stop
-            do_exit(1, true);
+            let proc_data = &thr.proc_data;
+            if proc_data.stop(signo) {
+                notify_job_change(proc_data, JobReport::Stopped(signo));
+                // Get the other threads to their next stop point
+                for tid in proc_data.proc.threads() {
+                    if let Ok(task) = get_task(tid) {
+                        task.interrupt();
+                    }
+                }
+            }
+            wait_while_stopped(thr);
         }
         SignalOSAction::Continue => {
-            // TODO: implement continue
+            // Already continued when `SIGCONT` was sent
         }
         SignalOSAction::Handler => {
             // do nothing
@@ -40,6 +61,105 @@ pub fn check_signals(
     true
 }
 
+/// Settles a system call that failed with `ERESTART` because a signal
+/// interrupted it, before the signal is delivered.
+///
+/// The call is resumed by `restart_syscall(2)` once the signal handlers
+/// return, unless one of the pending signals runs a handler without
+/// `SA_RESTART`, in which case it fails with `EINTR`.
+pub fn prepare_restart(thr: &Thread, uctx: &mut UserContext) {
+    if uctx.retval() as isize != -(LinuxError::ERESTART.code() as isize) {
+        return;
+    }
+    let pending = thr.signal.pending() & !thr.signal.blocked();
+    let actions = thr.proc_data.signal.actions.lock();
+    let restart = (1..=64)
+        .filter_map(Signo::from_repr)
+        .filter(|&signo| pending.has(signo))
+        .all(|signo| {
+            let action = &actions[signo];
+            !matches!(action.disposition, SignalDisposition::Handler(_))
+                || action.flags.contains(SignalActionFlags::RESTART)
+        });
+    drop(actions);
+
+    if restart {
+        set_restart_syscall(uctx);
+    } else {
+        thr.set_restart(None);
+        uctx.set_retval(-LinuxError::EINTR.code() as _);
+    }
+}
+
+/// Rewinds `uctx` to the system call instruction and makes it call
+/// `restart_syscall(2)` instead.
+fn set_restart_syscall(uctx: &mut UserContext) {
+    const NR: usize = Sysno::restart_syscall as usize;
+    // Clear `ERESTART`, which `rt_sigreturn` would otherwise bring back
+    uctx.set_retval(0);
+    #[cfg(target_arch = "x86_64")]
+    {
+        uctx.rax = NR as _;
+        uctx.set_ip(uctx.ip() - 2);
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        uctx.r[8] = NR as _;
+        uctx.set_ip(uctx.ip() - 4);
+    }
+    #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+    {
+        uctx.regs.a7 = NR as _;
+        uctx.set_ip(uctx.ip() - 4);
+    }
+}
+
+/// Blocks the current thread while its process is stopped by a job-control
+/// signal. `SIGCONT` and `SIGKILL` wake it up.
+pub fn wait_while_stopped(thr: &Thread) {
+    let proc_data = &thr.proc_data;
+    if !proc_data.is_stopped() {
+        return;
+    }
+    block_on(poll_fn(|cx| {
+        proc_data.stop_event.register(cx.waker());
+        if proc_data.is_stopped() && !thr.pending_exit() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }));
+}
+
+/// Builds the `SignalInfo` of a synchronous fault, with `si_code` and the
+/// faulting address filled in the way `SA_SIGINFO` handlers expect.
+pub fn fault_signal_info(signo: Signo, code: u32, addr: usize) -> SignalInfo {
+    kernel_signal_info(signo, code, SignalFields::Fault { addr })
+}
+
+/// Builds the `SignalInfo` sent to a parent when its child `pid` exits with
+/// the wait status `wstatus`.
+pub fn child_signal_info(signo: Signo, pid: Pid, wstatus: i32) -> SignalInfo {
+    let (code, status) = if wstatus & 0x7f == 0 {
+        (CLD_EXITED, (wstatus >> 8) & 0xff)
+    } else if wstatus & 0x80 != 0 {
+        (CLD_DUMPED, wstatus & 0x7f)
+    } else {
+        (CLD_KILLED, wstatus & 0x7f)
+    };
+    kernel_signal_info(signo, code, SignalFields::Child { pid, status })
+}
+
+/// Sends `SIGPIPE` to the current process after writing to a closed peer.
+pub fn raise_sigpipe() {
+    let curr = current();
+    send_signal_to_process(
+        curr.as_thread().proc_data.proc.pid(),
+        Some(SignalInfo::new_kernel(Signo::SIGPIPE)),
+    )
+    .expect("Failed to send SIGPIPE");
+}
+
 static BLOCK_NEXT_SIGNAL_CHECK: AtomicBool = AtomicBool::new(false);
 
 pub fn block_next_signal() {
@@ -149,6 +149,11 @@ impl SocketAddrExt for UnixSocketAddr {
         if read_family(addr, addrlen)? as u32 != AF_UNIX {
             return Err(AxError::from(LinuxError::EAFNOSUPPORT));
         }
+        if addrlen as usize > size_of::<sockaddr_un>() {
+            return Err(AxError::InvalidInput);
+        }
+        // For abstract names the full `addrlen` defines the name, embedded NULs
+        // included.
         let offset = size_of::<__kernel_sa_family_t>();
         let ptr = UserConstPtr::<u8>::from(addr.address().as_usize() + offset);
         let data = ptr.get_as_slice(addrlen as usize - offset)?;
@@ -173,7 +178,7 @@ impl SocketAddrExt for UnixSocketAddr {
             UnixSocketAddr::Path(path) => 1 + path.len(),
         };
         let mut buf = Vec::with_capacity(size_of::<__kernel_sa_family_t>() + data_len);
-        buf.extend_from_slice(&AF_UNIX.to_ne_bytes());
+        buf.extend_from_slice(&(AF_UNIX as __kernel_sa_family_t).to_ne_bytes());
         match self {
             UnixSocketAddr::Unnamed => {}
             UnixSocketAddr::Abstract(name) => {
@@ -18,7 +18,11 @@ use starry_core::task::AsThread;
 use starry_vm::{VmPtr, vm_write_slice};
 
 use crate::{
-    file::{Directory, FileLike, get_file_like, resolve_at, with_fs},
+    file::{
+        Directory, FileLike, forget_dir_nlinks, get_file_like,
+        inotify::{next_cookie, notify, notify_parent, watching},
+        resolve_at, with_fs,
+    },
     mm::vm_load_string,
     time::TimeValueLike,
 };
@@ -93,12 +97,15 @@ pub fn sys_mkdirat(dirfd: i32, path: *const c_char, mode: u32) -> AxResult<isize
     let mode = mode & !current().as_thread().proc_data.umask();
     let mode = NodePermission::from_bits_truncate(mode as u16);
 
-    with_fs(dirfd, |fs| {
-        fs.create_dir(path, mode)?;
-        Ok(0)
-    })
+    with_fs(dirfd, |fs| fs.create_dir(&path, mode))?;
+    forget_dir_nlinks();
+    notify_parent(dirfd, &path, IN_CREATE | IN_ISDIR);
+    Ok(0)
 }
 
+/// Upper bound on the bytes of `linux_dirent64` records returned per call.
+const MAX_DIRENTS_BATCH: usize = 64 * 1024;
+
 // Directory buffer for getdents64 syscall
 struct DirBuffer {
     buf: Vec<u8>,
@@ -134,6 +141,7 @@ impl DirBuffer {
                 d_ino,
                 d_off,
                 d_reclen: len as _,
+                // `NodeType` discriminants are the `DT_*` values
                 d_type: d_type as _,
                 d_name: Default::default(),
             });
@@ -151,7 +159,9 @@ impl DirBuffer {
 pub fn sys_getdents64(fd: i32, buf: *mut u8, len: usize) -> AxResult<isize> {
     debug!("sys_getdents64 <= fd: {fd}, buf: {buf:?}, len: {len}");
 
-    let mut buffer = DirBuffer::new(len);
+    // A record for the longest name is far below the batch size, so there is
+    // no need to allocate whatever size userspace asked for
+    let mut buffer = DirBuffer::new(len.min(MAX_DIRENTS_BATCH));
 
     let dir = Directory::from_fd(fd)?;
     let mut dir_offset = dir.offset.lock();
@@ -172,7 +182,9 @@ pub fn sys_getdents64(fd: i32, buf: *mut u8, len: usize) -> AxResult<isize> {
         return Err(AxError::InvalidInput);
     }
 
-    vm_write_slice(buf, &buffer.buf)?;
+    // Only the records written are copied out, the rest of the user buffer is
+    // left untouched
+    vm_write_slice(buf, &buffer.buf[..buffer.offset])?;
 
     Ok(buffer.offset as _)
 }
@@ -210,6 +222,7 @@ pub fn sys_linkat(
         with_fs(new_dirfd, |fs| fs.resolve_nonexistent(Path::new(&new_path)))?;
 
     new_dir.link(new_name, &old)?;
+    notify(&new_dir, IN_CREATE, 0, Some(new_name));
     Ok(0)
 }
 
@@ -228,14 +241,16 @@ pub fn sys_unlinkat(dirfd: i32, path: *const c_char, flags: usize) -> AxResult<i
 
     debug!("sys_unlinkat <= dirfd: {dirfd}, path: {path:?}, flags: {flags}");
 
-    with_fs(dirfd, |fs| {
-        if flags == AT_REMOVEDIR as _ {
-            fs.remove_dir(path)?;
-        } else {
-            fs.remove_file(path)?;
-        }
-        Ok(0)
-    })
+    let mask = if flags == AT_REMOVEDIR as _ {
+        with_fs(dirfd, |fs| fs.remove_dir(&path))?;
+        forget_dir_nlinks();
+        IN_DELETE | IN_ISDIR
+    } else {
+        with_fs(dirfd, |fs| fs.remove_file(&path))?;
+        IN_DELETE
+    };
+    notify_parent(dirfd, &path, mask);
+    Ok(0)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -283,10 +298,9 @@ pub fn sys_symlinkat(
     let linkpath = vm_load_string(linkpath)?;
     debug!("sys_symlinkat <= target: {target:?}, new_dirfd: {new_dirfd}, linkpath: {linkpath:?}");
 
-    with_fs(new_dirfd, |fs| {
-        fs.symlink(target, linkpath)?;
-        Ok(0)
-    })
+    with_fs(new_dirfd, |fs| fs.symlink(target, &linkpath))?;
+    notify_parent(new_dirfd, &linkpath, IN_CREATE);
+    Ok(0)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -304,13 +318,22 @@ pub fn sys_readlinkat(
 
     debug!("sys_readlinkat <= dirfd: {dirfd}, path: {path:?}");
 
-    with_fs(dirfd, |fs| {
-        let entry = fs.resolve_no_follow(path)?;
-        let link = entry.read_link()?;
-        let read = size.min(link.len());
-        vm_write_slice(buf, &link.as_bytes()[..read])?;
-        Ok(read as isize)
-    })
+    if size == 0 {
+        return Err(AxError::InvalidInput);
+    }
+
+    // An empty path reads the link referenced by an `O_PATH` dirfd
+    let loc = resolve_at(dirfd, Some(&path), AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH)?
+        .into_file()
+        .ok_or(AxError::InvalidInput)?;
+    if loc.node_type() != NodeType::Symlink {
+        return Err(AxError::InvalidInput);
+    }
+    // The target is silently truncated to the buffer size
+    let link = loc.read_link()?;
+    let read = size.min(link.len());
+    vm_write_slice(buf, &link.as_bytes()[..read])?;
+    Ok(read as isize)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -510,6 +533,12 @@ pub fn sys_renameat2(
         with_fs(new_dirfd, |fs| fs.resolve_nonexistent(Path::new(&new_path)))?;
 
     old_dir.rename(&old_name, &new_dir, new_name)?;
+    forget_dir_nlinks();
+    if watching() {
+        let cookie = next_cookie();
+        notify(&old_dir, IN_MOVED_FROM, cookie, Some(&*old_name));
+        notify(&new_dir, IN_MOVED_TO, cookie, Some(new_name));
+    }
     Ok(0)
 }
 
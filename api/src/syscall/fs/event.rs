@@ -17,6 +17,11 @@ bitflags! {
     }
 }
 
+/// Create an eventfd object.
+///
+/// Unknown bits in `flags` are rejected with `EINVAL`. `EFD_NONBLOCK` and
+/// `EFD_CLOEXEC` are applied before the descriptor becomes visible, so there is
+/// no window where a concurrent `execve` could observe it without them.
 pub fn sys_eventfd2(initval: u32, flags: u32) -> AxResult<isize> {
     debug!("sys_eventfd2 <= initval: {initval}, flags: {flags}");
 
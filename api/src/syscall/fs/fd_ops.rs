@@ -6,8 +6,9 @@ use core::{
 };
 
 use axerrno::{AxError, AxResult};
-use axfs::{FS_CONTEXT, FileBackend, OpenOptions, OpenResult};
+use axfs::{FS_CONTEXT, FileBackend, FileFlags, OpenOptions, OpenResult};
 use axfs_ng_vfs::{DirEntry, FileNode, Location, NodePermission, NodeType, Reference};
+use axio::{Seek, SeekFrom};
 use axtask::current;
 use bitflags::bitflags;
 use linux_raw_sys::general::*;
@@ -15,12 +16,20 @@ use starry_core::{task::AsThread, vfs::Device};
 
 use crate::{
     file::{
-        Directory, FD_TABLE, File, FileLike, Pipe, add_file_like, close_file_like, get_file_like,
-        with_fs,
+        ClosedFds, Directory, FD_CLOSED, FD_TABLE, File, FileLike, Pipe, add_file_like,
+        close_file_like,
+        fd_within_limit, get_file_like,
+        inotify::{notify_parent, watching},
+        ioring::IoRing,
+        lock::{
+            FlockKind, RecordLock, RecordOwner, flock, funlock, set_record_lock, test_record_lock,
+            unset_record_lock,
+        },
+        resolve_follow, with_fs,
     },
     mm::{UserPtr, vm_load_string},
     syscall::sys::{sys_getegid, sys_geteuid},
-    vfs::dev::tty,
+    vfs::dev::{self, tty},
 };
 
 /// Convert open flags to [`OpenOptions`].
@@ -62,10 +71,16 @@ fn flags_to_options(flags: c_int, mode: __kernel_mode_t, (uid, gid): (u32, u32))
 
 fn add_to_fd(result: OpenResult, flags: u32) -> AxResult<i32> {
     let f: Arc<dyn FileLike> = match result {
+        // `O_PATH` only references the node, devices are not opened
+        OpenResult::File(file) if flags & O_PATH != 0 => Arc::new(File::new_path(file)),
         OpenResult::File(mut file) => {
             // /dev/xx handling
             if let Ok(device) = file.location().entry().downcast::<Device>() {
                 let inner = device.inner().as_any();
+                if inner.is::<dev::IoRingDevice>() {
+                    // Every open of /dev/ioring is an independent instance
+                    return add_file_like(Arc::new(IoRing::default()), flags & O_CLOEXEC != 0);
+                }
                 if let Some(ptmx) = inner.downcast_ref::<tty::Ptmx>() {
                     // Opening /dev/ptmx creates a new pseudo-terminal
                     let (master, pty_number) = ptmx.create_pty()?;
@@ -125,10 +140,61 @@ pub fn sys_openat(
 
     let mode = mode & !current().as_thread().proc_data.umask();
 
-    let options = flags_to_options(flags, mode, (sys_geteuid()? as _, sys_getegid()? as _));
-    with_fs(dirfd, |fs| options.open(fs, path))
-        .and_then(|it| add_to_fd(it, flags as _))
-        .map(|fd| fd as isize)
+    let flags_u = flags as u32;
+    let no_follow = flags_u & O_NOFOLLOW != 0;
+    let ids = (sys_geteuid()? as _, sys_getegid()? as _);
+    let (result, created) = with_fs(dirfd, |fs| {
+        let existing = if no_follow {
+            fs.resolve_no_follow(&path)
+        } else {
+            resolve_follow(fs, &path)
+        };
+        match existing {
+            Ok(loc) => {
+                // Open the node just resolved by its name in its parent, so
+                // the path is not walked a second time
+                let options = flags_to_options(flags | O_NOFOLLOW as c_int, mode, ids);
+                match loc.parent() {
+                    Some(dir) => options.open(&fs.with_current_dir(dir)?, loc.name()),
+                    // Only the root has no parent
+                    None => options.open(fs, "/"),
+                }
+                .map(|result| (result, false))
+            }
+            Err(err) if err == AxError::NotFound && flags_u & O_CREAT != 0 => {
+                flags_to_options(flags, mode, ids)
+                    .open(fs, &path)
+                    .map(|result| (result, true))
+            }
+            Err(err) => Err(err),
+        }
+    })?;
+
+    // Checked on the node that was opened, not on an earlier lookup
+    let loc = match &result {
+        OpenResult::File(file) => file.location(),
+        OpenResult::Dir(dir) => dir,
+    };
+    let path_only = flags_u & O_PATH != 0;
+    let is_dir = loc.node_type() == NodeType::Directory;
+    // `O_NOFOLLOW` refuses a trailing symlink unless only the path is wanted
+    if no_follow && !path_only && loc.node_type() == NodeType::Symlink {
+        return Err(AxError::FilesystemLoop);
+    }
+    if flags_u & O_DIRECTORY != 0 && !is_dir {
+        return Err(AxError::NotADirectory);
+    }
+    let writable = flags_u & 0b11 != O_RDONLY;
+    let creat = flags_u & (O_CREAT | O_EXCL) == O_CREAT;
+    if is_dir && !path_only && (writable || creat) {
+        return Err(AxError::IsADirectory);
+    }
+
+    let fd = add_to_fd(result, flags_u)?;
+    if created && watching() {
+        notify_parent(dirfd, &path, IN_CREATE);
+    }
+    Ok(fd as isize)
 }
 
 /// Open a file by `filename` and insert it into the file descriptor table.
@@ -164,12 +230,17 @@ pub fn sys_close_range(first: i32, last: i32, flags: u32) -> AxResult<isize> {
         // TODO: optimize
         let curr = current();
         let mut scope = curr.as_thread().proc_data.scope.write();
-        let mut guard = FD_TABLE.scope_mut(&mut scope);
-        let old_files = mem::take(guard.deref_mut());
-        old_files.write().clone_from(old_files.read().deref());
+        {
+            let mut guard = FD_TABLE.scope_mut(&mut scope);
+            let old_files = mem::take(guard.deref_mut());
+            old_files.write().clone_from(old_files.read().deref());
+        }
+        // Pollers sharing the old table do not care about closes in this one
+        *FD_CLOSED.scope_mut(&mut scope) = Arc::default();
     }
 
     let cloexec = flags.contains(CloseRangeFlags::CLOEXEC);
+    let mut closed = ClosedFds::default();
     let mut fd_table = FD_TABLE.write();
     if let Some(max_index) = fd_table.ids().next_back() {
         for fd in first..=last.min(max_index as i32) {
@@ -178,7 +249,7 @@ pub fn sys_close_range(first: i32, last: i32, flags: u32) -> AxResult<isize> {
                     f.cloexec = true;
                 }
             } else {
-                fd_table.remove(fd as _);
+                closed.take(&mut fd_table, fd as _);
             }
         }
     }
@@ -220,7 +291,11 @@ pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> AxResult<isize> {
     if old_fd == new_fd {
         return Err(AxError::InvalidInput);
     }
+    if new_fd < 0 || !fd_within_limit(new_fd as _) {
+        return Err(AxError::BadFileDescriptor);
+    }
 
+    let mut closed = ClosedFds::default();
     let mut fd_table = FD_TABLE.write();
     let mut f = fd_table
         .get(old_fd as _)
@@ -228,7 +303,7 @@ pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> AxResult<isize> {
         .ok_or(AxError::BadFileDescriptor)?;
     f.cloexec = flags.contains(Dup3Flags::O_CLOEXEC);
 
-    fd_table.remove(new_fd as _);
+    closed.take(&mut fd_table, new_fd as _);
     fd_table
         .add_at(new_fd as _, f)
         .map_err(|_| AxError::BadFileDescriptor)?;
@@ -236,21 +311,125 @@ pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> AxResult<isize> {
     Ok(new_fd as _)
 }
 
+/// Converts the range of a `flock64` into `[start, end)` within `f`.
+fn record_range(f: &File, lock: &flock64) -> AxResult<(u64, u64)> {
+    let base = match lock.l_whence as u32 {
+        SEEK_SET => 0,
+        SEEK_CUR => f.inner().seek(SeekFrom::Current(0))? as i64,
+        SEEK_END => f.stat()?.size as i64,
+        _ => return Err(AxError::InvalidInput),
+    };
+    let start = base.checked_add(lock.l_start).ok_or(AxError::InvalidInput)?;
+    let (start, end) = match lock.l_len {
+        // A length of 0 extends the lock to the end of the file, wherever that
+        // ends up being.
+        0 => (start, None),
+        len if len > 0 => (start, Some(start.checked_add(len).ok_or(AxError::InvalidInput)?)),
+        len => (start.checked_add(len).ok_or(AxError::InvalidInput)?, Some(start)),
+    };
+    if start < 0 {
+        return Err(AxError::InvalidInput);
+    }
+    Ok((start as u64, end.map_or(u64::MAX, |end| end as u64)))
+}
+
+fn fcntl_lock(fd: c_int, cmd: u32, arg: usize) -> AxResult<isize> {
+    let f = File::from_fd(fd)?;
+    let lock = UserPtr::<flock64>::from(arg).get_as_mut()?;
+    let stat = f.stat()?;
+    let node = (stat.dev, stat.ino);
+    let (start, end) = record_range(&f, lock)?;
+
+    let owner = if matches!(cmd, F_OFD_SETLK | F_OFD_SETLKW | F_OFD_GETLK) {
+        if lock.l_pid != 0 {
+            return Err(AxError::InvalidInput);
+        }
+        RecordOwner::File(Arc::as_ptr(&f) as usize)
+    } else {
+        RecordOwner::Process(current().as_thread().proc_data.proc.pid())
+    };
+    let kind = match lock.l_type as u32 {
+        F_RDLCK => Some(FlockKind::Shared),
+        F_WRLCK => Some(FlockKind::Exclusive),
+        F_UNLCK => None,
+        _ => return Err(AxError::InvalidInput),
+    };
+    if matches!(cmd, F_SETLK | F_SETLKW | F_OFD_SETLK | F_OFD_SETLKW) {
+        // A read lock needs the file open for reading, a write lock for writing
+        let needed = match kind {
+            Some(FlockKind::Shared) => Some(FileFlags::READ),
+            Some(FlockKind::Exclusive) => Some(FileFlags::WRITE),
+            None => None,
+        };
+        if needed.is_some_and(|flags| f.inner().access(flags).is_err()) {
+            return Err(AxError::BadFileDescriptor);
+        }
+    }
+
+    match cmd {
+        F_GETLK | F_OFD_GETLK => {
+            let kind = kind.ok_or(AxError::InvalidInput)?;
+            let probe = RecordLock {
+                start,
+                end,
+                kind,
+                owner,
+            };
+            if let Some(held) = test_record_lock(node, &probe) {
+                lock.l_type = match held.kind {
+                    FlockKind::Shared => F_RDLCK,
+                    FlockKind::Exclusive => F_WRLCK,
+                } as _;
+                lock.l_whence = SEEK_SET as _;
+                lock.l_start = held.start as _;
+                lock.l_len = if held.end == u64::MAX {
+                    0
+                } else {
+                    (held.end - held.start) as _
+                };
+                lock.l_pid = match held.owner {
+                    RecordOwner::Process(pid) => pid as _,
+                    RecordOwner::File(_) => -1,
+                };
+            } else {
+                lock.l_type = F_UNLCK as _;
+            }
+        }
+        _ => {
+            let nonblocking = matches!(cmd, F_SETLK | F_OFD_SETLK);
+            match kind {
+                Some(kind) => {
+                    let record = RecordLock {
+                        start,
+                        end,
+                        kind,
+                        owner,
+                    };
+                    set_record_lock(node, record, nonblocking)?;
+                }
+                None => unset_record_lock(node, owner, start, end),
+            }
+        }
+    }
+    Ok(0)
+}
+
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> AxResult<isize> {
     debug!("sys_fcntl <= fd: {fd} cmd: {cmd} arg: {arg}");
 
     match cmd as u32 {
         F_DUPFD => dup_fd(fd, false),
         F_DUPFD_CLOEXEC => dup_fd(fd, true),
-        F_SETLK | F_SETLKW => Ok(0),
-        F_OFD_SETLK | F_OFD_SETLKW => Ok(0),
-        F_GETLK | F_OFD_GETLK => {
-            let arg = UserPtr::<flock64>::from(arg);
-            arg.get_as_mut()?.l_type = F_UNLCK as _;
-            Ok(0)
+        F_SETLK | F_SETLKW | F_GETLK | F_OFD_SETLK | F_OFD_SETLKW | F_OFD_GETLK => {
+            fcntl_lock(fd, cmd as u32, arg)
         }
         F_SETFL => {
-            get_file_like(fd)?.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
+            let f = get_file_like(fd)?;
+            f.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
+            if let Some(owner) = f.owner() {
+                owner.set_async(arg & (FASYNC as usize) > 0);
+                owner.arm(&*f);
+            }
             Ok(0)
         }
         F_GETFL => {
@@ -260,6 +439,9 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> AxResult<isize> {
             if f.nonblocking() {
                 ret |= O_NONBLOCK;
             }
+            if f.owner().is_some_and(|owner| owner.is_async()) {
+                ret |= FASYNC;
+            }
 
             let perm = NodePermission::from_bits_truncate(f.stat()?.mode as _);
             if perm.contains(NodePermission::OWNER_WRITE) {
@@ -289,6 +471,27 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> AxResult<isize> {
                 .cloexec = cloexec;
             Ok(0)
         }
+        F_SETOWN => {
+            let f = get_file_like(fd)?;
+            let owner = f.owner().ok_or(AxError::InvalidInput)?;
+            owner.set_owner(arg as i32);
+            owner.arm(&*f);
+            Ok(0)
+        }
+        F_GETOWN => {
+            let f = get_file_like(fd)?;
+            Ok(f.owner().map_or(0, |owner| owner.owner()) as _)
+        }
+        F_ADD_SEALS => {
+            let f = File::from_fd(fd).map_err(|_| AxError::InvalidInput)?;
+            let memfd = f.memfd().ok_or(AxError::InvalidInput)?;
+            memfd.add_seals(arg as u32)?;
+            Ok(0)
+        }
+        F_GET_SEALS => {
+            let f = File::from_fd(fd).map_err(|_| AxError::InvalidInput)?;
+            Ok(f.memfd().ok_or(AxError::InvalidInput)?.seals() as _)
+        }
         F_GETPIPE_SZ => {
             let pipe = Pipe::from_fd(fd)?;
             Ok(pipe.capacity() as _)
@@ -307,6 +510,23 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> AxResult<isize> {
 
 pub fn sys_flock(fd: c_int, operation: c_int) -> AxResult<isize> {
     debug!("flock <= fd: {fd}, operation: {operation}");
-    // TODO: flock
+    let f = get_file_like(fd)?;
+    if f.downcast_ref::<File>().is_none() && f.downcast_ref::<Directory>().is_none() {
+        // TODO: flock on other file types
+        return Ok(0);
+    }
+    let stat = f.stat()?;
+    let node = (stat.dev, stat.ino);
+    // Locks belong to the open file description, which is shared by dup and fork.
+    let owner = Arc::as_ptr(&f) as *const () as usize;
+
+    let operation = operation as u32;
+    let nonblocking = operation & LOCK_NB != 0;
+    match operation & !LOCK_NB {
+        LOCK_SH => flock(node, owner, FlockKind::Shared, nonblocking)?,
+        LOCK_EX => flock(node, owner, FlockKind::Exclusive, nonblocking)?,
+        LOCK_UN => funlock(node, owner),
+        _ => return Err(AxError::InvalidInput),
+    }
     Ok(0)
 }
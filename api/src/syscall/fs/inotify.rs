@@ -0,0 +1,69 @@
+use core::ffi::c_char;
+
+use axerrno::{AxError, AxResult};
+use bitflags::bitflags;
+use linux_raw_sys::general::{
+    AT_FDCWD, AT_SYMLINK_NOFOLLOW, IN_ALL_EVENTS, IN_CLOEXEC, IN_DONT_FOLLOW, IN_NONBLOCK,
+    IN_ONLYDIR,
+};
+
+use crate::{
+    file::{FileLike, add_file_like, inotify::Inotify, resolve_at},
+    mm::vm_load_string,
+};
+
+bitflags! {
+    /// Flags for the `inotify_init1` syscall.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct InotifyFlags: u32 {
+        /// Create a file descriptor that is closed on `exec`.
+        const CLOEXEC = IN_CLOEXEC;
+        /// Create a non-blocking inotify instance.
+        const NONBLOCK = IN_NONBLOCK;
+    }
+}
+
+pub fn sys_inotify_init1(flags: u32) -> AxResult<isize> {
+    debug!("sys_inotify_init1 <= flags: {flags:#x}");
+
+    let flags = InotifyFlags::from_bits(flags).ok_or(AxError::InvalidInput)?;
+
+    let inotify = Inotify::new();
+    inotify.set_nonblocking(flags.contains(InotifyFlags::NONBLOCK))?;
+    add_file_like(inotify as _, flags.contains(InotifyFlags::CLOEXEC)).map(|fd| fd as _)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_inotify_init() -> AxResult<isize> {
+    sys_inotify_init1(0)
+}
+
+pub fn sys_inotify_add_watch(fd: i32, path: *const c_char, mask: u32) -> AxResult<isize> {
+    let path = vm_load_string(path)?;
+    debug!("sys_inotify_add_watch <= fd: {fd}, path: {path:?}, mask: {mask:#x}");
+
+    let inotify = Inotify::from_fd(fd)?;
+    if mask & IN_ALL_EVENTS == 0 {
+        return Err(AxError::InvalidInput);
+    }
+
+    let flags = if mask & IN_DONT_FOLLOW != 0 {
+        AT_SYMLINK_NOFOLLOW
+    } else {
+        0
+    };
+    let loc = resolve_at(AT_FDCWD, Some(path.as_str()), flags)?
+        .into_file()
+        .ok_or(AxError::InvalidInput)?;
+    if mask & IN_ONLYDIR != 0 && !loc.is_dir() {
+        return Err(AxError::NotADirectory);
+    }
+    inotify.add_watch(&loc, mask).map(|wd| wd as _)
+}
+
+pub fn sys_inotify_rm_watch(fd: i32, wd: i32) -> AxResult<isize> {
+    debug!("sys_inotify_rm_watch <= fd: {fd}, wd: {wd}");
+
+    Inotify::from_fd(fd)?.rm_watch(wd)?;
+    Ok(0)
+}
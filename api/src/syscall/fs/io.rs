@@ -9,12 +9,15 @@ use axfs::{FS_CONTEXT, FileFlags, OpenOptions};
 use axio::{Seek, SeekFrom};
 use axpoll::{IoEvents, Pollable};
 use axtask::current;
-use linux_raw_sys::general::__kernel_off_t;
+use linux_raw_sys::general::{IN_MODIFY, SPLICE_F_NONBLOCK, __kernel_off_t};
 use starry_vm::{VmMutPtr, VmPtr};
 use syscalls::Sysno;
 
 use crate::{
-    file::{File, FileLike, Pipe, get_file_like},
+    file::{
+        Directory, File, FileLike, IoSrc, Pipe, Socket, get_file_like, inotify::notify_file,
+        write_all,
+    },
     io::{IoVec, IoVectorBuf},
     mm::{UserConstPtr, VmBytes, VmBytesMut},
 };
@@ -63,14 +66,23 @@ pub fn sys_readv(fd: i32, iov: *const IoVec, iovcnt: usize) -> AxResult<isize> {
 /// Return the written size if success.
 pub fn sys_write(fd: i32, buf: *mut u8, len: usize) -> AxResult<isize> {
     debug!("sys_write <= fd: {fd}, buf: {buf:p}, len: {len}");
-    Ok(get_file_like(fd)?.write(&mut VmBytes::new(buf, len))? as _)
+    write_stream(&*get_file_like(fd)?, &mut VmBytes::new(buf, len)).map(|n| n as _)
 }
 
 pub fn sys_writev(fd: i32, iov: *const IoVec, iovcnt: usize) -> AxResult<isize> {
     debug!("sys_writev <= fd: {fd}, iovcnt: {iovcnt}");
     let f = get_file_like(fd)?;
-    f.write(&mut IoVectorBuf::new(iov, iovcnt)?.into_io())
-        .map(|n| n as _)
+    write_stream(&*f, &mut IoVectorBuf::new(iov, iovcnt)?.into_io()).map(|n| n as _)
+}
+
+/// Writes to `f`, retrying short writes on pipes and stream sockets, where
+/// they are common at buffer boundaries. Datagrams are sent whole or not at all.
+fn write_stream(f: &dyn FileLike, src: &mut IoSrc) -> AxResult<usize> {
+    if f.is::<Pipe>() || f.downcast_ref::<Socket>().is_some_and(Socket::is_stream) {
+        write_all(f, src)
+    } else {
+        f.write(src)
+    }
 }
 
 pub fn sys_lseek(fd: c_int, offset: __kernel_off_t, whence: c_int) -> AxResult<isize> {
@@ -81,6 +93,9 @@ pub fn sys_lseek(fd: c_int, offset: __kernel_off_t, whence: c_int) -> AxResult<i
         2 => SeekFrom::End(offset as _),
         _ => return Err(AxError::InvalidInput),
     };
+    if let Ok(dir) = Directory::from_fd(fd) {
+        return Ok(dir.seek(pos)? as _);
+    }
     let off = File::from_fd(fd)?.inner().seek(pos)?;
     Ok(off as _)
 }
@@ -96,13 +111,19 @@ pub fn sys_truncate(path: UserConstPtr<c_char>, length: __kernel_off_t) -> AxRes
         .open(&FS_CONTEXT.lock(), path)?
         .into_file()?;
     file.access(FileFlags::WRITE)?.set_len(length as _)?;
+    notify_file(file.location(), IN_MODIFY);
     Ok(0)
 }
 
 pub fn sys_ftruncate(fd: c_int, length: __kernel_off_t) -> AxResult<isize> {
     debug!("sys_ftruncate <= {fd} {length}");
     let f = File::from_fd(fd)?;
-    f.inner().access(FileFlags::WRITE)?.set_len(length as _)?;
+    let file = f.inner().access(FileFlags::WRITE)?;
+    if let Some(memfd) = f.memfd() {
+        memfd.check_resize(file.location().len()?, length as _)?;
+    }
+    file.set_len(length as _)?;
+    f.notify_watches(IN_MODIFY);
     Ok(0)
 }
 
@@ -113,13 +134,10 @@ pub fn sys_fallocate(
     len: __kernel_off_t,
 ) -> AxResult<isize> {
     debug!("sys_fallocate <= fd: {fd}, mode: {mode}, offset: {offset}, len: {len}");
-    if mode != 0 {
+    if offset < 0 || len <= 0 {
         return Err(AxError::InvalidInput);
     }
-    let f = File::from_fd(fd)?;
-    let inner = f.inner();
-    let file = inner.access(FileFlags::WRITE)?;
-    file.set_len(file.location().len()?.max(offset as u64 + len as u64))?;
+    get_file_like(fd)?.fallocate(mode, offset as _, len as _)?;
     Ok(0)
 }
 
@@ -168,11 +186,13 @@ pub fn sys_pwrite64(
     len: usize,
     offset: __kernel_off_t,
 ) -> AxResult<isize> {
+    if offset < 0 {
+        return Err(AxError::InvalidInput);
+    }
     if len == 0 {
         return Ok(0);
     }
-    let f = File::from_fd(fd)?;
-    let write = f.inner().write_at(VmBytes::new(buf, len), offset as _)?;
+    let write = File::from_fd(fd)?.write_at(VmBytes::new(buf, len), offset as _)?;
     Ok(write as _)
 }
 
@@ -216,10 +236,11 @@ pub fn sys_pwritev2(
     _flags: u32,
 ) -> AxResult<isize> {
     debug!("sys_pwritev2 <= fd: {fd}, iovcnt: {iovcnt}, offset: {offset}, flags: {_flags}");
+    if offset < 0 {
+        return Err(AxError::InvalidInput);
+    }
     let f = File::from_fd(fd)?;
-    f.inner()
-        .read_at(IoVectorBuf::new(iov, iovcnt)?.into_io(), offset as _)
-        .map(|n| n as _)
+    f.write_at(IoVectorBuf::new(iov, iovcnt)?.into_io(), offset as _).map(|n| n as _)
 }
 
 enum SendFile {
@@ -253,7 +274,7 @@ impl SendFile {
             SendFile::Direct(file) => file.write(&mut buf),
             SendFile::Offset(file, offset) => {
                 let off = offset.vm_read()?;
-                let bytes_written = file.inner().write_at(buf, off)?;
+                let bytes_written = file.write_at(buf, off)?;
                 offset.vm_write(off + bytes_written as u64)?;
                 Ok(bytes_written)
             }
@@ -301,13 +322,17 @@ pub fn sys_sendfile(out_fd: c_int, in_fd: c_int, offset: *mut u64, len: usize) -
         len
     );
 
+    // The input has to be a seekable file, pipes and sockets are rejected
+    // with `EINVAL`.
+    let in_file = File::from_fd(in_fd)?;
+
     let src = if !offset.is_null() {
         if offset.vm_read()? > u32::MAX as u64 {
             return Err(AxError::InvalidInput);
         }
-        SendFile::Offset(File::from_fd(in_fd)?, offset)
+        SendFile::Offset(in_file, offset)
     } else {
-        SendFile::Direct(get_file_like(in_fd)?)
+        SendFile::Direct(in_file)
     };
 
     let dst = SendFile::Direct(get_file_like(out_fd)?);
@@ -321,7 +346,7 @@ pub fn sys_copy_file_range(
     fd_out: c_int,
     off_out: *mut u64,
     len: usize,
-    _flags: u32,
+    flags: u32,
 ) -> AxResult<isize> {
     debug!(
         "sys_copy_file_range <= fd_in: {}, off_in: {}, fd_out: {}, off_out: {}, len: {}, flags: {}",
@@ -330,23 +355,45 @@ pub fn sys_copy_file_range(
         fd_out,
         !off_out.is_null(),
         len,
-        _flags
+        flags
     );
 
-    // TODO: check flags
-    // TODO: check both regular files
-    // TODO: check same file and overlap
+    if flags != 0 {
+        return Err(AxError::InvalidInput);
+    }
+    // Both ends have to be regular files, pipes and sockets are rejected with
+    // `EINVAL`.
+    let in_file = File::from_fd(fd_in)?;
+    let out_file = File::from_fd(fd_out)?;
+
+    let in_meta = in_file.inner().location().metadata()?;
+    let out_meta = out_file.inner().location().metadata()?;
+    if (in_meta.device, in_meta.inode) == (out_meta.device, out_meta.inode) {
+        let position = |file: &File, off: *mut u64| -> AxResult<u64> {
+            if off.is_null() {
+                Ok(file.inner().seek(SeekFrom::Current(0))?)
+            } else {
+                off.vm_read()
+            }
+        };
+        let pos_in = position(&in_file, off_in)?;
+        let pos_out = position(&out_file, off_out)?;
+        let len = len as u64;
+        if pos_in < pos_out.saturating_add(len) && pos_out < pos_in.saturating_add(len) {
+            return Err(AxError::InvalidInput);
+        }
+    }
 
     let src = if !off_in.is_null() {
-        SendFile::Offset(File::from_fd(fd_in)?, off_in)
+        SendFile::Offset(in_file, off_in)
     } else {
-        SendFile::Direct(get_file_like(fd_in)?)
+        SendFile::Direct(in_file)
     };
 
     let dst = if !off_out.is_null() {
-        SendFile::Offset(File::from_fd(fd_out)?, off_out)
+        SendFile::Offset(out_file, off_out)
     } else {
-        SendFile::Direct(get_file_like(fd_out)?)
+        SendFile::Direct(out_file)
     };
 
     do_send(src, dst, len).map(|n| n as _)
@@ -358,7 +405,7 @@ pub fn sys_splice(
     fd_out: c_int,
     off_out: *mut i64,
     len: usize,
-    _flags: u32,
+    flags: u32,
 ) -> AxResult<isize> {
     debug!(
         "sys_splice <= fd_in: {}, off_in: {}, fd_out: {}, off_out: {}, len: {}, flags: {}",
@@ -367,16 +414,17 @@ pub fn sys_splice(
         fd_out,
         !off_out.is_null(),
         len,
-        _flags
+        flags
     );
 
-    let mut has_pipe = false;
+    let mut src_pipe = None;
+    let mut dst_pipe = None;
 
     if DummyFd::from_fd(fd_in).is_ok() || DummyFd::from_fd(fd_out).is_ok() {
         return Err(AxError::BadFileDescriptor);
     }
 
-    let src = if !off_in.is_null() {
+    let mut src = if !off_in.is_null() {
         if off_in.vm_read()? < 0 {
             return Err(AxError::InvalidInput);
         }
@@ -386,7 +434,7 @@ pub fn sys_splice(
             if !src.is_read() {
                 return Err(AxError::BadFileDescriptor);
             }
-            has_pipe = true;
+            src_pipe = Some(src);
         }
         if let Ok(file) = File::from_fd(fd_in)
             && file.inner().is_path()
@@ -396,7 +444,7 @@ pub fn sys_splice(
         SendFile::Direct(get_file_like(fd_in)?)
     };
 
-    let dst = if !off_out.is_null() {
+    let mut dst = if !off_out.is_null() {
         if off_out.vm_read()? < 0 {
             return Err(AxError::InvalidInput);
         }
@@ -406,7 +454,7 @@ pub fn sys_splice(
             if !dst.is_write() {
                 return Err(AxError::BadFileDescriptor);
             }
-            has_pipe = true;
+            dst_pipe = Some(dst);
         }
         if let Ok(file) = File::from_fd(fd_out)
             && file.inner().access(FileFlags::APPEND).is_ok()
@@ -418,9 +466,17 @@ pub fn sys_splice(
         SendFile::Direct(f)
     };
 
-    if !has_pipe {
-        return Err(AxError::InvalidInput);
+    let nonblocking = flags & SPLICE_F_NONBLOCK != 0;
+    match (src_pipe, dst_pipe) {
+        (Some(src), Some(dst)) => src.splice_to(&dst, len, nonblocking || src.nonblocking()),
+        (Some(pipe), None) => {
+            pipe.splice_out(len, nonblocking || pipe.nonblocking(), |buf| dst.write(buf))
+        }
+        (None, Some(pipe)) => {
+            pipe.splice_in(len, nonblocking || pipe.nonblocking(), |buf| src.read(buf))
+        }
+        // Linux requires at least one end to be a pipe.
+        (None, None) => Err(AxError::InvalidInput),
     }
-
-    do_send(src, dst, len).map(|n| n as _)
+    .map(|n| n as _)
 }
@@ -1,32 +1,42 @@
-use alloc::format;
 use core::ffi::c_char;
 
 use axerrno::{AxError, AxResult};
-use axfs::{FS_CONTEXT, OpenOptions};
-use linux_raw_sys::general::MFD_CLOEXEC;
+use bitflags::bitflags;
+use linux_raw_sys::general::{MFD_ALLOW_SEALING, MFD_CLOEXEC};
 
 use crate::{
-    file::{File, FileLike},
-    mm::UserConstPtr,
+    file::{FileLike, memfd::create_memfd},
+    mm::vm_load_string,
 };
 
-// TODO: correct memfd implementation
+/// The maximum length of a memfd name, excluding the `memfd:` prefix.
+const MFD_NAME_MAX: usize = 249;
 
-pub fn sys_memfd_create(_name: UserConstPtr<c_char>, flags: u32) -> AxResult<isize> {
-    // This is cursed
-    for id in 0..0xffff {
-        let name = format!("/tmp/memfd-{id:04x}");
-        let fs = FS_CONTEXT.lock().clone();
-        if fs.resolve(&name).is_err() {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(&fs, &name)?
-                .into_file()?;
-            let cloexec = flags & MFD_CLOEXEC != 0;
-            return File::new(file).add_to_fd_table(cloexec).map(|fd| fd as _);
-        }
+bitflags! {
+    /// Flags for the `memfd_create` syscall.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct MemfdFlags: u32 {
+        /// Create a file descriptor that is closed on `exec`.
+        const CLOEXEC = MFD_CLOEXEC;
+        /// Allow sealing operations on the file.
+        const ALLOW_SEALING = MFD_ALLOW_SEALING;
     }
-    Err(AxError::TooManyOpenFiles)
+}
+
+/// Create an anonymous file backed by memory.
+///
+/// `name` is only used for display purposes, e.g. as the `/memfd:<name>` link
+/// target in `/proc/self/fd`.
+pub fn sys_memfd_create(name: *const c_char, flags: u32) -> AxResult<isize> {
+    let name = vm_load_string(name)?;
+    debug!("sys_memfd_create <= name: {name:?}, flags: {flags:#x}");
+
+    let flags = MemfdFlags::from_bits(flags).ok_or(AxError::InvalidInput)?;
+    if name.len() > MFD_NAME_MAX {
+        return Err(AxError::InvalidInput);
+    }
+
+    create_memfd(name, flags.contains(MemfdFlags::ALLOW_SEALING))?
+        .add_to_fd_table(flags.contains(MemfdFlags::CLOEXEC))
+        .map(|fd| fd as _)
 }
@@ -1,6 +1,7 @@
 mod ctl;
 mod event;
 mod fd_ops;
+mod inotify;
 mod io;
 mod memfd;
 mod mount;
@@ -8,7 +9,9 @@ mod pidfd;
 mod pipe;
 mod signalfd;
 mod stat;
+mod timerfd;
 
 pub use self::{
-    ctl::*, event::*, fd_ops::*, io::*, memfd::*, mount::*, pidfd::*, pipe::*, signalfd::*, stat::*,
+    ctl::*, event::*, fd_ops::*, inotify::*, io::*, memfd::*, mount::*, pidfd::*, pipe::*,
+    signalfd::*, stat::*, timerfd::*,
 };
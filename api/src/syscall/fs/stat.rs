@@ -1,16 +1,16 @@
 use core::ffi::{c_char, c_int};
 
 use axerrno::{AxError, AxResult};
-use axfs::FS_CONTEXT;
-use axfs_ng_vfs::{Location, NodePermission};
 use linux_raw_sys::general::{
-    __kernel_fsid_t, AT_EMPTY_PATH, R_OK, W_OK, X_OK, stat, statfs, statx,
+    AT_EACCESS, AT_EMPTY_PATH, AT_SYMLINK_NOFOLLOW, R_OK, S_IFDIR, S_IFMT, W_OK, X_OK, stat,
+    statfs, statx,
 };
 use starry_vm::{VmMutPtr, VmPtr};
 
 use crate::{
-    file::{File, FileLike, resolve_at},
+    file::{get_file_like, resolve_at},
     mm::vm_load_string,
+    syscall::sys::{supplementary_groups, sys_getegid, sys_geteuid, sys_getgid, sys_getuid},
 };
 
 /// Get the file metadata by `path` and write into `statbuf`.
@@ -109,67 +109,68 @@ pub fn sys_faccessat2(dirfd: c_int, path: *const c_char, mode: u32, flags: u32)
     let path = path.nullable().map(vm_load_string).transpose()?;
     debug!("sys_faccessat2 <= dirfd: {dirfd}, path: {path:?}, mode: {mode}, flags: {flags}");
 
+    if mode & !(R_OK | W_OK | X_OK) != 0
+        || flags & !(AT_EACCESS | AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0
+    {
+        return Err(AxError::InvalidInput);
+    }
+
     let file = resolve_at(dirfd, path.as_deref(), flags)?;
 
+    // F_OK only checks for existence
     if mode == 0 {
         return Ok(0);
     }
-    let mut required_mode = NodePermission::empty();
-    if mode & R_OK != 0 {
-        required_mode |= NodePermission::OWNER_READ;
-    }
-    if mode & W_OK != 0 {
-        required_mode |= NodePermission::OWNER_WRITE;
-    }
-    if mode & X_OK != 0 {
-        required_mode |= NodePermission::OWNER_EXEC;
+
+    // Checks use the real ids unless `AT_EACCESS` asks for the effective ones
+    let (uid, gid) = if flags & AT_EACCESS != 0 {
+        (sys_geteuid()? as u32, sys_getegid()? as u32)
+    } else {
+        (sys_getuid()? as u32, sys_getgid()? as u32)
+    };
+
+    let stat = file.stat()?;
+    if uid == 0 {
+        // Root may read and write anything, but executes only if some `x` bit
+        // is set or the node is a directory
+        let is_dir = stat.mode & S_IFMT == S_IFDIR;
+        if mode & X_OK != 0 && !is_dir && stat.mode & 0o111 == 0 {
+            return Err(AxError::PermissionDenied);
+        }
+        return Ok(0);
     }
-    let required_mode = required_mode.bits();
-    if (file.stat()?.mode as u16 & required_mode) != required_mode {
+
+    // Only the class the caller falls into is consulted. Supplementary groups
+    // count for the group class like the primary one.
+    let shift = if stat.uid == uid {
+        6
+    } else if stat.gid == gid || supplementary_groups().contains(&stat.gid) {
+        3
+    } else {
+        0
+    };
+    let granted = (stat.mode >> shift) & 0o7;
+    if granted & mode != mode {
         return Err(AxError::PermissionDenied);
     }
 
     Ok(0)
 }
 
-fn statfs(loc: &Location) -> AxResult<statfs> {
-    let stat = loc.filesystem().stat()?;
-    // FIXME: Zeroable
-    let mut result: statfs = unsafe { core::mem::zeroed() };
-    result.f_type = stat.fs_type as _;
-    result.f_bsize = stat.block_size as _;
-    result.f_blocks = stat.blocks as _;
-    result.f_bfree = stat.blocks_free as _;
-    result.f_bavail = stat.blocks_available as _;
-    result.f_files = stat.file_count as _;
-    result.f_ffree = stat.free_file_count as _;
-    // TODO: fsid
-    result.f_fsid = __kernel_fsid_t {
-        val: [0, loc.mountpoint().device() as _],
-    };
-    result.f_namelen = stat.name_length as _;
-    result.f_frsize = stat.fragment_size as _;
-    result.f_flags = stat.mount_flags as _;
-    Ok(result)
-}
-
 pub fn sys_statfs(path: *const c_char, buf: *mut statfs) -> AxResult<isize> {
+    use linux_raw_sys::general::AT_FDCWD;
+
     let path = vm_load_string(path)?;
     debug!("sys_statfs <= path: {path:?}");
 
-    buf.vm_write(statfs(
-        &FS_CONTEXT
-            .lock()
-            .resolve(path)?
-            .mountpoint()
-            .root_location(),
-    )?)?;
+    let statfs = resolve_at(AT_FDCWD, Some(path.as_str()), 0)?.statfs()?;
+    buf.vm_write(statfs.into())?;
     Ok(0)
 }
 
 pub fn sys_fstatfs(fd: i32, buf: *mut statfs) -> AxResult<isize> {
     debug!("sys_fstatfs <= fd: {fd}");
 
-    buf.vm_write(statfs(File::from_fd(fd)?.inner().location())?)?;
+    buf.vm_write(get_file_like(fd)?.statfs()?.into())?;
     Ok(0)
 }
@@ -0,0 +1,103 @@
+use axerrno::{AxError, AxResult};
+use axhal::time::{TimeValue, monotonic_time, wall_time};
+use bitflags::bitflags;
+use linux_raw_sys::general::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_REALTIME, O_CLOEXEC, O_NONBLOCK, itimerspec, timespec,
+};
+use starry_vm::{VmMutPtr, VmPtr};
+
+use crate::{
+    file::{FileLike, add_file_like, timerfd::TimerFd},
+    time::TimeValueLike,
+};
+
+// TFD flag definitions (if not available in linux_raw_sys)
+const TFD_CLOEXEC: u32 = O_CLOEXEC;
+const TFD_NONBLOCK: u32 = O_NONBLOCK;
+const TFD_TIMER_ABSTIME: u32 = 1 << 0;
+const TFD_TIMER_CANCEL_ON_SET: u32 = 1 << 1;
+
+bitflags! {
+    /// Flags for the `timerfd_create` syscall.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct TimerfdFlags: u32 {
+        /// Create a file descriptor that is closed on `exec`.
+        const CLOEXEC = TFD_CLOEXEC;
+        /// Create a non-blocking timerfd.
+        const NONBLOCK = TFD_NONBLOCK;
+    }
+}
+
+bitflags! {
+    /// Flags for the `timerfd_settime` syscall.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct TimerfdSetFlags: u32 {
+        /// Interpret the expiration as an absolute time of the timer's clock.
+        const ABSTIME = TFD_TIMER_ABSTIME;
+        /// Accepted, but discontinuous changes of the realtime clock are not
+        /// reported.
+        const CANCEL_ON_SET = TFD_TIMER_CANCEL_ON_SET;
+    }
+}
+
+fn itimerspec_from(interval: TimeValue, value: TimeValue) -> itimerspec {
+    itimerspec {
+        it_interval: timespec::from_time_value(interval),
+        it_value: timespec::from_time_value(value),
+    }
+}
+
+/// Create a timer that delivers its expirations via a file descriptor.
+pub fn sys_timerfd_create(clockid: u32, flags: u32) -> AxResult<isize> {
+    debug!("sys_timerfd_create <= clockid: {clockid}, flags: {flags:#x}");
+
+    let clock = match clockid {
+        CLOCK_REALTIME => wall_time,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => monotonic_time,
+        _ => return Err(AxError::InvalidInput),
+    };
+    let flags = TimerfdFlags::from_bits(flags).ok_or(AxError::InvalidInput)?;
+
+    let timerfd = TimerFd::new(clock);
+    timerfd.set_nonblocking(flags.contains(TimerfdFlags::NONBLOCK))?;
+    add_file_like(timerfd as _, flags.contains(TimerfdFlags::CLOEXEC)).map(|fd| fd as _)
+}
+
+/// Arm or disarm the timer referred to by `fd`.
+///
+/// A zero `it_value` disarms the timer. Otherwise it is the first expiration,
+/// either relative to now or, with `TFD_TIMER_ABSTIME`, an absolute time of
+/// the timer's clock; a nonzero `it_interval` makes the timer periodic.
+pub fn sys_timerfd_settime(
+    fd: i32,
+    flags: u32,
+    new_value: *const itimerspec,
+    old_value: *mut itimerspec,
+) -> AxResult<isize> {
+    let flags = TimerfdSetFlags::from_bits(flags).ok_or(AxError::InvalidInput)?;
+    // FIXME: AnyBitPattern
+    let new_value = unsafe { new_value.vm_read_uninit()?.assume_init() };
+    let interval = new_value.it_interval.try_into_time_value()?;
+    let value = new_value.it_value.try_into_time_value()?;
+    debug!(
+        "sys_timerfd_settime <= fd: {fd}, flags: {flags:?}, interval: {interval:?}, value: \
+         {value:?}"
+    );
+
+    let timerfd = TimerFd::from_fd(fd)?;
+    let old = timerfd.set(interval, value, flags.contains(TimerfdSetFlags::ABSTIME));
+    if let Some(old_value) = old_value.nullable() {
+        old_value.vm_write(itimerspec_from(old.0, old.1))?;
+    }
+    Ok(0)
+}
+
+/// Return the time until the next expiration of the timer referred to by
+/// `fd`, along with its interval.
+pub fn sys_timerfd_gettime(fd: i32, curr_value: *mut itimerspec) -> AxResult<isize> {
+    debug!("sys_timerfd_gettime <= fd: {fd}");
+
+    let (interval, remaining) = TimerFd::from_fd(fd)?.get();
+    curr_value.vm_write(itimerspec_from(interval, remaining))?;
+    Ok(0)
+}
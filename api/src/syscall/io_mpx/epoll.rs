@@ -52,7 +52,8 @@ pub fn sys_epoll_ctl(
             EpollFlags::from_bits(event.events & !events.bits()).ok_or(AxError::InvalidInput)?;
         Ok((
             EpollEvent {
-                events,
+                // Errors and hangups are always reported, requested or not.
+                events: events | IoEvents::ERR | IoEvents::HUP,
                 user_data: event.data,
             },
             flags,
@@ -8,7 +8,7 @@ use core::task::Context;
 use axpoll::{IoEvents, Pollable};
 
 pub use self::{epoll::*, poll::*, select::*};
-use crate::file::FileLike;
+use crate::file::{FileLike, register_close_waker};
 
 struct FdPollSet(pub Vec<(Arc<dyn FileLike>, IoEvents)>);
 impl Pollable for FdPollSet {
@@ -20,5 +20,7 @@ impl Pollable for FdPollSet {
         for (file, events) in &self.0 {
             file.register(context, *events);
         }
+        // Re-evaluate when one of the fds is closed by another thread
+        register_close_waker(context.waker());
     }
 }
@@ -1,4 +1,4 @@
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
 
 use axerrno::{AxError, AxResult};
 use axhal::time::TimeValue;
@@ -24,6 +24,7 @@ fn do_poll(
     debug!("do_poll fds={poll_fds:?} timeout={timeout:?}");
 
     let mut res = 0isize;
+    let mut fd_nums = Vec::with_capacity(poll_fds.len());
     let mut fds = Vec::with_capacity(poll_fds.len());
     let mut revents = Vec::with_capacity(poll_fds.len());
     for fd in poll_fds.iter_mut() {
@@ -33,6 +34,7 @@ fn do_poll(
         }
         match get_file_like(fd.fd) {
             Ok(f) => {
+                fd_nums.push(fd.fd);
                 fds.push((
                     f,
                     IoEvents::from_bits(fd.events as _).ok_or(AxError::InvalidInput)?
@@ -57,7 +59,15 @@ fn do_poll(
             timeout,
             poll_io(&fds, IoEvents::empty(), false, || {
                 let mut res = 0usize;
-                for ((fd, events), revents) in fds.0.iter().zip(revents.iter_mut()) {
+                for (((fd, events), revents), fd_num) in
+                    fds.0.iter().zip(revents.iter_mut()).zip(&fd_nums)
+                {
+                    // The fd was closed (or replaced) while we were waiting
+                    if !get_file_like(*fd_num).is_ok_and(|f| Arc::ptr_eq(&f, fd)) {
+                        **revents = POLLNVAL as _;
+                        res += 1;
+                        continue;
+                    }
                     let mut result = fd.poll();
                     if result.contains(IoEvents::IN) {
                         result |= IoEvents::RDNORM;
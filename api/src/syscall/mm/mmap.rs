@@ -8,12 +8,16 @@ use axtask::current;
 use linux_raw_sys::general::*;
 use memory_addr::{MemoryAddr, VirtAddr, VirtAddrRange, align_up_4k};
 use starry_core::{
+    mm::{FileMap, add_file_map, forget_file_maps},
     task::AsThread,
     vfs::{Device, DeviceMmap},
 };
 use starry_vm::{vm_load, vm_write_slice};
 
-use crate::file::{File, FileLike};
+use crate::file::{
+    File, FileLike,
+    memfd::{check_protect, forget_shared_maps},
+};
 
 bitflags::bitflags! {
     /// `PROT_*` flags for use with [`sys_mmap`].
@@ -152,6 +156,8 @@ pub fn sys_mmap(
         let dst_addr = VirtAddr::from(start);
         if !map_flags.contains(MmapFlags::FIXED_NOREPLACE) {
             aspace.unmap(dst_addr, length)?;
+            forget_shared_maps(&curr.as_thread().proc_data.aspace, dst_addr, length);
+            forget_file_maps(&curr.as_thread().proc_data.aspace, dst_addr, length);
         }
         dst_addr
     } else {
@@ -173,11 +179,32 @@ pub fn sys_mmap(
     };
 
     let file = if fd > 0 {
-        Some(File::from_fd(fd)?)
+        let file = File::from_fd(fd)?;
+        if file.is_path() {
+            return Err(AxError::BadFileDescriptor);
+        }
+        Some(file)
     } else {
         None
     };
 
+    // Shared mappings of a memfd are subject to its write seals, once writable.
+    let memfd_map = file
+        .clone()
+        .filter(|file| file.memfd().is_some() && map_type != MmapFlags::PRIVATE);
+    if let Some(file) = &memfd_map
+        && permission_flags.contains(MmapProt::WRITE)
+        && file.memfd().unwrap().seals() & (F_SEAL_WRITE | F_SEAL_FUTURE_WRITE) != 0
+    {
+        return Err(AxError::OperationNotPermitted);
+    }
+
+    // Identifies the file in `/proc/[pid]/maps`
+    let file_id = file
+        .as_ref()
+        .map(|file| file.stat().map(|stat| (stat.dev, stat.ino, file.path().into_owned())))
+        .transpose()?;
+
     let backend = match map_type {
         MmapFlags::SHARED | MmapFlags::SHARED_VALIDATE => {
             if let Some(file) = file {
@@ -245,6 +272,22 @@ pub fn sys_mmap(
 
     let populate = map_flags.contains(MmapFlags::POPULATE);
     aspace.map(start, length, permission_flags.into(), populate, backend)?;
+    if let Some((dev, ino, path)) = file_id {
+        let map = FileMap {
+            start,
+            end: start + length,
+            offset: offset as u64,
+            dev,
+            ino,
+            path,
+        };
+        add_file_map(&curr.as_thread().proc_data.aspace, map);
+    }
+    if let Some(file) = memfd_map {
+        file.memfd()
+            .unwrap()
+            .add_shared_map(&curr.as_thread().proc_data.aspace, start, length);
+    }
 
     Ok(start.as_usize() as _)
 }
@@ -256,6 +299,8 @@ pub fn sys_munmap(addr: usize, length: usize) -> AxResult<isize> {
     let length = align_up_4k(length);
     let start_addr = VirtAddr::from(addr);
     aspace.unmap(start_addr, length)?;
+    forget_shared_maps(&curr.as_thread().proc_data.aspace, start_addr, length);
+    forget_file_maps(&curr.as_thread().proc_data.aspace, start_addr, length);
     Ok(0)
 }
 
@@ -274,6 +319,9 @@ pub fn sys_mprotect(addr: usize, length: usize, prot: u32) -> AxResult<isize> {
     let mut aspace = curr.as_thread().proc_data.aspace.lock();
     let length = align_up_4k(length);
     let start_addr = VirtAddr::from(addr);
+    if permission_flags.contains(MmapProt::WRITE) {
+        check_protect(&curr.as_thread().proc_data.aspace, start_addr, length)?;
+    }
     aspace.protect(start_addr, length, permission_flags.into())?;
 
     Ok(0)
@@ -1,5 +1,6 @@
 mod brk;
 mod mincore;
 mod mmap;
+mod process_vm;
 
-pub use self::{brk::*, mincore::*, mmap::*};
+pub use self::{brk::*, mincore::*, mmap::*, process_vm::*};
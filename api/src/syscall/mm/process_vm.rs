@@ -0,0 +1,141 @@
+use alloc::{vec, vec::Vec};
+
+use axerrno::{AxError, AxResult};
+use axtask::current;
+use memory_addr::PAGE_SIZE_4K;
+use starry_core::task::{AsThread, ProcessData, get_process_data};
+use starry_process::Pid;
+use starry_vm::{VmPtr, vm_load, vm_write_slice};
+
+use crate::{
+    io::IoVec,
+    mm::{read_remote, write_remote},
+    syscall::{sys::sys_geteuid, task::traces_process},
+};
+
+/// Same as `IOV_MAX` in Linux.
+const IOV_MAX: usize = 1024;
+
+fn load_iovs(iov: *const IoVec, iovcnt: usize) -> AxResult<Vec<IoVec>> {
+    if iovcnt > IOV_MAX {
+        return Err(AxError::InvalidInput);
+    }
+    (0..iovcnt)
+        .map(|i| {
+            let iov = iov.wrapping_add(i).vm_read()?;
+            if iov.iov_len < 0 {
+                return Err(AxError::InvalidInput);
+            }
+            Ok(iov)
+        })
+        .collect()
+}
+
+/// Only the process itself, its tracer and root may access its memory.
+fn check_remote_access(target: &ProcessData) -> AxResult<()> {
+    let curr = current();
+    if curr.as_thread().proc_data.proc.pid() == target.proc.pid()
+        || traces_process(target)
+        || sys_geteuid()? == 0
+    {
+        Ok(())
+    } else {
+        Err(AxError::OperationNotPermitted)
+    }
+}
+
+/// Copies between the local iovecs and the ones in the address space of `pid`,
+/// in order, until either side runs out or a remote page is inaccessible.
+fn process_vm_copy(
+    pid: Pid,
+    local_iov: *const IoVec,
+    liovcnt: usize,
+    remote_iov: *const IoVec,
+    riovcnt: usize,
+    flags: u32,
+    write: bool,
+) -> AxResult<isize> {
+    if flags != 0 {
+        return Err(AxError::InvalidInput);
+    }
+    let local = load_iovs(local_iov, liovcnt)?;
+    let remote = load_iovs(remote_iov, riovcnt)?;
+
+    let target = get_process_data(pid)?;
+    check_remote_access(&target)?;
+    let aspace = target.aspace.clone();
+
+    let mut copied = 0;
+    let mut local = local.iter().filter(|iov| iov.iov_len > 0);
+    let mut cur_local = local.next().copied();
+    let mut local_off = 0;
+    'remote: for r in remote.iter().filter(|iov| iov.iov_len > 0) {
+        let mut remote_off = 0;
+        while remote_off < r.iov_len as usize {
+            let Some(l) = cur_local else {
+                break 'remote;
+            };
+            let len = (r.iov_len as usize - remote_off)
+                .min(l.iov_len as usize - local_off)
+                .min(PAGE_SIZE_4K);
+            let local_ptr = l.iov_base.wrapping_add(local_off);
+            let remote_addr = r.iov_base as usize + remote_off;
+
+            let done = if write {
+                let buf = vm_load(local_ptr, len)?;
+                write_remote(&aspace, remote_addr, &buf)
+            } else {
+                let mut buf = vec![0; len];
+                let done = read_remote(&aspace, remote_addr, &mut buf);
+                vm_write_slice(local_ptr, &buf[..done])?;
+                done
+            };
+            copied += done;
+            if done < len {
+                // A partial transfer reports what was copied so far
+                if copied == 0 {
+                    return Err(AxError::BadAddress);
+                }
+                break 'remote;
+            }
+
+            remote_off += len;
+            local_off += len;
+            if local_off == l.iov_len as usize {
+                cur_local = local.next().copied();
+                local_off = 0;
+            }
+        }
+    }
+    Ok(copied as _)
+}
+
+pub fn sys_process_vm_readv(
+    pid: Pid,
+    local_iov: *const IoVec,
+    liovcnt: usize,
+    remote_iov: *const IoVec,
+    riovcnt: usize,
+    flags: u32,
+) -> AxResult<isize> {
+    debug!(
+        "sys_process_vm_readv <= pid: {pid}, liovcnt: {liovcnt}, riovcnt: {riovcnt}, flags: \
+         {flags:#x}"
+    );
+    process_vm_copy(pid, local_iov, liovcnt, remote_iov, riovcnt, flags, false)
+}
+
+pub fn sys_process_vm_writev(
+    pid: Pid,
+    local_iov: *const IoVec,
+    liovcnt: usize,
+    remote_iov: *const IoVec,
+    riovcnt: usize,
+    flags: u32,
+) -> AxResult<isize> {
+    debug!(
+        "sys_process_vm_writev <= pid: {pid}, liovcnt: {liovcnt}, riovcnt: {riovcnt}, flags: \
+         {flags:#x}"
+    );
+    process_vm_copy(pid, local_iov, liovcnt, remote_iov, riovcnt, flags, true)
+}
@@ -5,13 +5,15 @@ use axerrno::{AxError, AxResult};
 use axio::prelude::*;
 use axnet::{CMsgData, RecvFlags, RecvOptions, SendFlags, SendOptions, SocketAddrEx, SocketOps};
 use linux_raw_sys::net::{
-    MSG_PEEK, MSG_TRUNC, SCM_RIGHTS, SOL_SOCKET, cmsghdr, msghdr, sockaddr, socklen_t,
+    MSG_CMSG_CLOEXEC, MSG_NOSIGNAL, MSG_PEEK, MSG_TRUNC, SCM_RIGHTS, SOL_SOCKET, cmsghdr, msghdr,
+    sockaddr, socklen_t,
 };
 
 use crate::{
     file::{FileLike, Socket, add_file_like},
     io::{IoVec, IoVectorBuf},
     mm::{UserConstPtr, UserPtr, VmBytes, VmBytesMut},
+    signal::raise_sigpipe,
     socket::SocketAddrExt,
     syscall::net::{CMsg, CMsgBuilder},
 };
@@ -33,14 +35,20 @@ fn send_impl(
     debug!("sys_send <= fd: {fd}, flags: {flags}, addr: {addr:?}");
 
     let socket = Socket::from_fd(fd)?;
-    let sent = socket.send(
-        &mut src,
-        SendOptions {
-            to: addr,
-            flags: SendFlags::default(),
-            cmsg,
-        },
-    )?;
+    let sent = socket
+        .send(
+            &mut src,
+            SendOptions {
+                to: addr,
+                flags: SendFlags::default(),
+                cmsg,
+            },
+        )
+        .inspect_err(|err| {
+            if matches!(err, AxError::BrokenPipe) && flags & MSG_NOSIGNAL == 0 {
+                raise_sigpipe();
+            }
+        })?;
 
     Ok(sent as isize)
 }
@@ -104,6 +112,7 @@ fn recv_impl(
 
     let mut remote_addr =
         (!addr.is_null()).then(|| SocketAddrEx::Ip((Ipv4Addr::UNSPECIFIED, 0).into()));
+    let len = dst.remaining_mut();
     let recv = socket.recv(
         &mut dst,
         RecvOptions {
@@ -112,6 +121,11 @@ fn recv_impl(
             cmsg: Some(&mut cmsg),
         },
     )?;
+    socket.note_read(len, recv);
+
+    if let Some(owner) = socket.owner() {
+        owner.arm(&*socket);
+    }
 
     if let Some(remote_addr) = remote_addr {
         remote_addr.write_to_user(addr, addrlen.get_as_mut()?)?;
@@ -128,7 +142,7 @@ fn recv_impl(
                 CMsg::Rights { fds } => builder.push(SOL_SOCKET, SCM_RIGHTS, |data| {
                     let mut written = 0;
                     for (f, chunk) in fds.into_iter().zip(data.chunks_exact_mut(size_of::<i32>())) {
-                        let fd = add_file_like(f, false)?;
+                        let fd = add_file_like(f, flags & MSG_CMSG_CLOEXEC != 0)?;
                         chunk.copy_from_slice(&fd.to_ne_bytes());
                         written += size_of::<i32>();
                     }
@@ -42,6 +42,26 @@ mod conv {
         }
     }
 
+    /// The default `net.core.rmem_max`/`wmem_max` on Linux.
+    const SOCK_BUF_MAX: usize = 212992;
+    const SOCK_BUF_MIN: usize = 2048;
+
+    /// A socket buffer size. Like Linux, the requested size is capped and then
+    /// doubled to leave room for bookkeeping, and `getsockopt` reports the
+    /// doubled value.
+    pub struct BufferSize;
+
+    impl BufferSize {
+        pub fn sys_to_rust(val: i32) -> AxResult<usize> {
+            let val = (val.max(0) as usize).min(SOCK_BUF_MAX);
+            Ok((val * 2).max(SOCK_BUF_MIN))
+        }
+
+        pub fn rust_to_sys(val: usize) -> AxResult<i32> {
+            Int::rust_to_sys(val)
+        }
+    }
+
     pub struct Duration;
 
     impl Duration {
@@ -85,8 +105,8 @@ macro_rules! call_dispatch {
             (SOL_SOCKET, SO_REUSEADDR) => ReuseAddress as IntBool,
             (SOL_SOCKET, SO_ERROR) => Error,
             (SOL_SOCKET, SO_DONTROUTE) => DontRoute as IntBool,
-            (SOL_SOCKET, SO_SNDBUF) => SendBuffer as Int<usize>,
-            (SOL_SOCKET, SO_RCVBUF) => ReceiveBuffer as Int<usize>,
+            (SOL_SOCKET, SO_SNDBUF) => SendBuffer as BufferSize,
+            (SOL_SOCKET, SO_RCVBUF) => ReceiveBuffer as BufferSize,
             (SOL_SOCKET, SO_KEEPALIVE) => KeepAlive as IntBool,
             (SOL_SOCKET, SO_RCVTIMEO) => ReceiveTimeout as Duration,
             (SOL_SOCKET, SO_SNDTIMEO) => SendTimeout as Duration,
@@ -112,6 +132,14 @@ macro_rules! call_dispatch {
     }
 }
 
+/// TCP-level options (e.g. `TCP_NODELAY`) only apply to TCP sockets.
+fn check_level(socket: &Socket, level: u32) -> AxResult<()> {
+    if level == PROTO_TCP && !matches!(**socket, axnet::Socket::Tcp(_)) {
+        return Err(AxError::from(LinuxError::ENOPROTOOPT));
+    }
+    Ok(())
+}
+
 pub fn sys_getsockopt(
     fd: i32,
     level: u32,
@@ -138,6 +166,7 @@ pub fn sys_getsockopt(
     }
 
     let socket = Socket::from_fd(fd)?;
+    check_level(&socket, level)?;
     macro_rules! dispatch {
         ($which:ident) => {
             socket.get_option(GetSocketOption::$which(get(optval, optlen)?))?;
@@ -177,6 +206,7 @@ pub fn sys_setsockopt(
     }
 
     let socket = Socket::from_fd(fd)?;
+    check_level(&socket, level)?;
     macro_rules! dispatch {
         ($which:ident) => {
             socket.set_option(SetSocketOption::$which(get(optval, optlen)?))?;
@@ -1,12 +1,15 @@
 use axerrno::{AxError, AxResult};
 use axhal::time::TimeValue;
 use axtask::current;
-use linux_raw_sys::general::{__kernel_old_timeval, RLIM_NLIMITS, rlimit64, rusage};
-use starry_core::task::{AsThread, Thread, get_process_data, get_task};
+use linux_raw_sys::general::{__kernel_old_timeval, RLIM_NLIMITS, RLIMIT_NOFILE, rlimit64, rusage};
+use starry_core::{
+    resources::AX_FILE_LIMIT,
+    task::{AsThread, Thread, get_process_data, get_task},
+};
 use starry_process::Pid;
 use starry_vm::{VmMutPtr, VmPtr};
 
-use crate::time::TimeValueLike;
+use crate::{syscall::sys::sys_geteuid, time::TimeValueLike};
 
 pub fn sys_prlimit64(
     pid: Pid,
@@ -19,51 +22,80 @@ pub fn sys_prlimit64(
     }
 
     let proc_data = get_process_data(pid)?;
-    if let Some(old_limit) = old_limit.nullable() {
-        let limit = &proc_data.rlim.read()[resource];
-        old_limit.vm_write(rlimit64 {
-            rlim_cur: limit.current,
-            rlim_max: limit.max,
-        })?;
-    }
+    // FIXME: AnyBitPattern
+    let new_limit = new_limit
+        .nullable()
+        .map(|it| unsafe { it.vm_read_uninit().map(|it| it.assume_init()) })
+        .transpose()?;
 
-    if let Some(new_limit) = new_limit.nullable() {
-        // FIXME: AnyBitPattern
-        let new_limit = unsafe { new_limit.vm_read_uninit()?.assume_init() };
+    if let Some(new_limit) = &new_limit {
         if new_limit.rlim_cur > new_limit.rlim_max {
             return Err(AxError::InvalidInput);
         }
+        // Like Linux's `nr_open`, the descriptor table size caps the hard
+        // `RLIMIT_NOFILE`.
+        if resource == RLIMIT_NOFILE && new_limit.rlim_max > AX_FILE_LIMIT as u64 {
+            return Err(AxError::OperationNotPermitted);
+        }
+    }
 
-        let limit = &mut proc_data.rlim.write()[resource];
-        if new_limit.rlim_max <= limit.max {
+    // The old value is read under the same lock the new one is stored with,
+    // but only copied out once the lock is released
+    let old = {
+        let mut rlim = proc_data.rlim.write();
+        let limit = &mut rlim[resource];
+        let old = rlimit64 {
+            rlim_cur: limit.current,
+            rlim_max: limit.max,
+        };
+        if let Some(new_limit) = new_limit {
+            // Only privileged callers may raise a hard limit, so lowering it
+            // is irreversible for everyone else
+            if new_limit.rlim_max > limit.max && sys_geteuid()? != 0 {
+                return Err(AxError::OperationNotPermitted);
+            }
+            // The soft `RLIMIT_NOFILE` is consulted by every new descriptor,
+            // so the change applies to the next allocation right away
             limit.max = new_limit.rlim_max;
-        } else {
-            // TODO: patch resources
-            // return Err(AxError::OperationNotPermitted);
-            return Ok(0);
+            limit.current = new_limit.rlim_cur;
         }
-
-        limit.current = new_limit.rlim_cur;
+        old
+    };
+    if let Some(old_limit) = old_limit.nullable() {
+        old_limit.vm_write(old)?;
     }
 
     Ok(0)
 }
 
+/// The resource usage the kernel tracks per thread.
+///
+/// Resident set sizes are not accounted, so `ru_maxrss` stays zero.
 #[derive(Default)]
 struct Rusage {
     utime: TimeValue,
     stime: TimeValue,
+    nvcsw: u64,
+    nivcsw: u64,
 }
 
 impl Rusage {
     fn from_thread(thread: &Thread) -> Self {
         let (utime, stime) = thread.time.borrow().output();
-        Self { utime, stime }
+        let (nvcsw, nivcsw) = thread.context_switches();
+        Self {
+            utime,
+            stime,
+            nvcsw,
+            nivcsw,
+        }
     }
 
     fn collate(mut self, other: Rusage) -> Self {
         self.utime += other.utime;
         self.stime += other.stime;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
         self
     }
 }
@@ -74,6 +106,8 @@ impl From<Rusage> for rusage {
         let mut usage: rusage = unsafe { core::mem::zeroed() };
         usage.ru_utime = __kernel_old_timeval::from_time_value(value.utime);
         usage.ru_stime = __kernel_old_timeval::from_time_value(value.stime);
+        usage.ru_nvcsw = value.nvcsw as _;
+        usage.ru_nivcsw = value.nivcsw as _;
         usage
     }
 }
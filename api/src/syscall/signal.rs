@@ -7,11 +7,11 @@ use axtask::{
     future::{self, block_on},
 };
 use linux_raw_sys::general::{
-    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, kernel_sigaction, siginfo,
-    timespec,
+    MINSIGSTKSZ, SA_NOCLDSTOP, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
+    kernel_sigaction, siginfo, timespec,
 };
 use starry_core::task::{
-    AsThread, processes, send_signal_to_process, send_signal_to_process_group,
+    AsThread, send_signal_to_all, send_signal_to_process, send_signal_to_process_group,
     send_signal_to_thread,
 };
 use starry_process::Pid;
@@ -81,12 +81,21 @@ pub fn sys_rt_sigaction(
     }
 
     let curr = current();
-    let mut actions = curr.as_thread().proc_data.signal.actions.lock();
+    let proc_data = &curr.as_thread().proc_data;
+    let mut actions = proc_data.signal.actions.lock();
     if let Some(oldact) = oldact.nullable() {
-        oldact.vm_write(actions[signo].clone().into())?;
+        let mut old: kernel_sigaction = actions[signo].clone().into();
+        if signo == Signo::SIGCHLD && proc_data.no_child_stop() {
+            old.sa_flags |= SA_NOCLDSTOP as _;
+        }
+        oldact.vm_write(old)?;
     }
     if let Some(act) = act.nullable() {
-        let act = unsafe { act.vm_read_uninit()?.assume_init() }.into();
+        let act = unsafe { act.vm_read_uninit()?.assume_init() };
+        if signo == Signo::SIGCHLD {
+            proc_data.set_no_child_stop((act.sa_flags & SA_NOCLDSTOP as _) != 0);
+        }
+        let act = act.into();
         debug!("sys_rt_sigaction <= signo: {signo:?}, act: {act:?}");
         actions[signo] = act;
     }
@@ -120,24 +129,15 @@ pub fn sys_kill(pid: i32, signo: u32) -> AxResult<isize> {
             send_signal_to_process(pid as _, sig)?;
         }
         0 => {
-            let pgid = current().as_thread().proc_data.proc.group().pgid();
-            send_signal_to_process_group(pgid, sig)?;
+            send_signal_to_process_group(0, sig)?;
         }
         -1 => {
-            let curr_pid = current().as_thread().proc_data.proc.pid();
-            if let Some(sig) = sig {
-                for proc_data in processes() {
-                    // POSIX.1 requires that kill(-1,sig) send sig to all processes that
-                    //    the calling process may send signals to, except possibly for some
-                    //    implementation-defined system processes.  Linux allows a process
-                    //    to signal itself, but on Linux the call kill(-1,sig) does not
-                    //    signal the calling process.
-                    if proc_data.proc.is_init() || proc_data.proc.pid() == curr_pid {
-                        continue;
-                    }
-                    let _ = send_signal_to_process(proc_data.proc.pid(), Some(sig.clone()));
-                }
-            }
+            // POSIX.1 requires that kill(-1,sig) send sig to all processes that
+            //    the calling process may send signals to, except possibly for some
+            //    implementation-defined system processes.  Linux allows a process
+            //    to signal itself, but on Linux the call kill(-1,sig) does not
+            //    signal the calling process.
+            send_signal_to_all(sig)?;
         }
         ..-1 => {
             send_signal_to_process_group((-pid) as Pid, sig)?;
@@ -211,6 +211,13 @@ pub fn sys_rt_sigreturn(uctx: &mut UserContext) -> AxResult<isize> {
     Ok(uctx.retval() as isize)
 }
 
+pub fn sys_restart_syscall() -> AxResult<isize> {
+    match current().as_thread().take_restart() {
+        Some(restart) => restart(),
+        None => Err(AxError::Interrupted),
+    }
+}
+
 pub fn sys_rt_sigtimedwait(
     uctx: &mut UserContext,
     set: *const SignalSet,
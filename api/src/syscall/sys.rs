@@ -37,13 +37,19 @@ pub fn sys_setgid(_gid: u32) -> AxResult<isize> {
     Ok(0)
 }
 
+/// The supplementary group ids of the current process.
+pub fn supplementary_groups() -> &'static [u32] {
+    &[0]
+}
+
 pub fn sys_getgroups(size: usize, list: *mut u32) -> AxResult<isize> {
     debug!("sys_getgroups <= size: {size}");
-    if size < 1 {
+    let groups = supplementary_groups();
+    if size < groups.len() {
         return Err(AxError::InvalidInput);
     }
-    vm_write_slice(list, &[0])?;
-    Ok(1)
+    vm_write_slice(list, groups)?;
+    Ok(groups.len() as _)
 }
 
 pub fn sys_setgroups(_size: usize, _list: *const u32) -> AxResult<isize> {
@@ -8,7 +8,7 @@ use bitflags::bitflags;
 use kspin::SpinNoIrq;
 use linux_raw_sys::general::*;
 use starry_core::{
-    mm::copy_from_kernel,
+    mm::{copy_from_kernel, fork_file_maps},
     task::{AsThread, ProcessData, Thread, add_task_to_table},
 };
 use starry_process::Pid;
@@ -16,7 +16,7 @@ use starry_signal::Signo;
 use starry_vm::VmMutPtr;
 
 use crate::{
-    file::{FD_TABLE, FileLike, PidFd},
+    file::{FD_CLOSED, FD_TABLE, FileLike, PidFd, clone_fd_table, memfd::fork_shared_maps},
     task::new_user_task,
 };
 
@@ -162,6 +162,8 @@ pub fn sys_clone(
             let mut aspace = old_proc_data.aspace.lock();
             let aspace = aspace.try_clone()?;
             copy_from_kernel(&mut aspace.lock())?;
+            fork_shared_maps(&old_proc_data.aspace, &aspace);
+            fork_file_maps(&old_proc_data.aspace, &aspace);
             aspace
         };
         new_task
@@ -182,6 +184,7 @@ pub fn sys_clone(
             exit_signal,
         );
         proc_data.set_umask(old_proc_data.umask());
+        proc_data.set_no_child_stop(old_proc_data.no_child_stop());
         // Inherit heap pointers from parent to ensure child's heap state is consistent after fork
         proc_data.set_heap_top(old_proc_data.get_heap_top());
 
@@ -189,11 +192,9 @@ pub fn sys_clone(
             let mut scope = proc_data.scope.write();
             if flags.contains(CloneFlags::FILES) {
                 FD_TABLE.scope_mut(&mut scope).clone_from(&FD_TABLE);
+                FD_CLOSED.scope_mut(&mut scope).clone_from(&FD_CLOSED);
             } else {
-                FD_TABLE
-                    .scope_mut(&mut scope)
-                    .write()
-                    .clone_from(&FD_TABLE.read());
+                *FD_TABLE.scope_mut(&mut scope).write() = clone_fd_table();
             }
 
             if flags.contains(CloneFlags::FS) {
@@ -8,7 +8,11 @@ use axtask::current;
 use starry_core::{config::USER_HEAP_BASE, mm::load_user_app, task::AsThread};
 use starry_vm::vm_load_until_nul;
 
-use crate::{file::FD_TABLE, mm::vm_load_string};
+use super::ptrace_exec;
+use crate::{
+    file::{ClosedFds, FD_TABLE, memfd::forget_all_shared_maps},
+    mm::vm_load_string,
+};
 
 pub fn sys_execve(
     uctx: &mut UserContext,
@@ -49,10 +53,9 @@ pub fn sys_execve(
         return Err(AxError::WouldBlock);
     }
 
-    let mut aspace = proc_data.aspace.lock();
     let (entry_point, user_stack_base) =
-        load_user_app(&mut aspace, Some(path.as_str()), &args, &envs)?;
-    drop(aspace);
+        load_user_app(&proc_data.aspace, Some(path.as_str()), &args, &envs)?;
+    forget_all_shared_maps(&proc_data.aspace);
 
     let loc = FS_CONTEXT.lock().resolve(&path)?;
     curr.set_name(loc.name());
@@ -63,22 +66,26 @@ pub fn sys_execve(
     proc_data.set_heap_top(USER_HEAP_BASE);
 
     *proc_data.signal.actions.lock() = Default::default();
+    proc_data.set_no_child_stop(false);
 
     // Clear set_child_tid after exec since the original address is no longer valid
     curr.as_thread().set_clear_child_tid(0);
 
     // Close CLOEXEC file descriptors
+    let mut closed = ClosedFds::default();
     let mut fd_table = FD_TABLE.write();
     let cloexec_fds = fd_table
         .ids()
         .filter(|it| fd_table.get(*it).unwrap().cloexec)
         .collect::<Vec<_>>();
     for fd in cloexec_fds {
-        fd_table.remove(fd);
+        closed.take(&mut fd_table, fd);
     }
     drop(fd_table);
+    drop(closed);
 
     uctx.set_ip(entry_point.as_usize());
     uctx.set_sp(user_stack_base.as_usize());
+    ptrace_exec();
     Ok(0)
 }
@@ -0,0 +1,461 @@
+//! A minimal `ptrace(2)`: tracing children, stopping them on `SIGTRAP`, and
+//! reading or writing their registers and memory while they are stopped.
+//!
+//! `PTRACE_GETREGS`/`PTRACE_SETREGS` exchange the architecture's
+//! `user_regs_struct`, which is only provided for x86_64 and riscv64 so far.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    future::poll_fn,
+    mem::size_of,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use axerrno::{AxError, AxResult, LinuxError};
+use axhal::uspace::UserContext;
+use axpoll::PollSet;
+use axsync::Mutex;
+use axtask::{
+    current,
+    future::{block_on, interruptible},
+};
+use starry_core::task::{
+    AsThread, JobReport, ProcessData, Thread, get_task, notify_job_change,
+};
+use starry_process::Pid;
+use starry_signal::Signo;
+use starry_vm::{VmMutPtr, VmPtr};
+
+use crate::mm::{force_write_remote, read_remote};
+
+const PTRACE_TRACEME: u32 = 0;
+const PTRACE_PEEKTEXT: u32 = 1;
+const PTRACE_PEEKDATA: u32 = 2;
+const PTRACE_POKETEXT: u32 = 4;
+const PTRACE_POKEDATA: u32 = 5;
+const PTRACE_CONT: u32 = 7;
+const PTRACE_SINGLESTEP: u32 = 9;
+const PTRACE_GETREGS: u32 = 12;
+const PTRACE_SETREGS: u32 = 13;
+const PTRACE_ATTACH: u32 = 16;
+const PTRACE_DETACH: u32 = 17;
+
+/// Trace state of one thread.
+struct Tracee {
+    /// The tracing process.
+    tracer: Pid,
+    /// The registers of the tracee while it is stopped.
+    regs: Mutex<Option<UserContext>>,
+    /// Set by the tracer to let a stopped tracee run again.
+    resumed: AtomicBool,
+    /// Whether to trap again after one instruction.
+    single_step: AtomicBool,
+    /// A stop to report at the next return to user, requested by
+    /// `PTRACE_ATTACH` or by `execve`.
+    pending_stop: Mutex<Option<Signo>>,
+    /// Woken when the tracee is resumed.
+    event: PollSet,
+}
+
+impl Tracee {
+    fn new(tracer: Pid) -> Arc<Self> {
+        Arc::new(Self {
+            tracer,
+            regs: Mutex::new(None),
+            resumed: AtomicBool::new(false),
+            single_step: AtomicBool::new(false),
+            pending_stop: Mutex::new(None),
+            event: PollSet::new(),
+        })
+    }
+
+    fn resume(&self, single_step: bool) {
+        self.single_step.store(single_step, Ordering::Release);
+        self.resumed.store(true, Ordering::Release);
+        self.event.wake();
+    }
+}
+
+/// `struct user_regs_struct` of x86_64.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::AnyBitPattern)]
+struct UserRegs {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbp: u64,
+    rbx: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rax: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    orig_rax: u64,
+    rip: u64,
+    cs: u64,
+    eflags: u64,
+    rsp: u64,
+    ss: u64,
+    fs_base: u64,
+    gs_base: u64,
+    ds: u64,
+    es: u64,
+    fs: u64,
+    gs: u64,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl UserRegs {
+    /// The flags a tracer may change: CF, PF, AF, ZF, SF, TF, DF, OF and AC.
+    const FLAGS_MASK: u64 = 0x4_0dd5;
+
+    fn from_context(uctx: &UserContext) -> Self {
+        Self {
+            r15: uctx.r15,
+            r14: uctx.r14,
+            r13: uctx.r13,
+            r12: uctx.r12,
+            rbp: uctx.rbp,
+            rbx: uctx.rbx,
+            r11: uctx.r11,
+            r10: uctx.r10,
+            r9: uctx.r9,
+            r8: uctx.r8,
+            rax: uctx.rax,
+            rcx: uctx.rcx,
+            rdx: uctx.rdx,
+            rsi: uctx.rsi,
+            rdi: uctx.rdi,
+            // Stops are never reported from inside a system call
+            orig_rax: u64::MAX,
+            rip: uctx.rip,
+            cs: uctx.cs,
+            eflags: uctx.rflags,
+            rsp: uctx.rsp,
+            ss: uctx.ss,
+            fs_base: uctx.tls() as _,
+            gs_base: uctx.gs_base,
+            ds: 0,
+            es: 0,
+            fs: 0,
+            gs: 0,
+        }
+    }
+
+    /// Writes the registers back into `uctx`. The segment selectors and the
+    /// privileged flags (IF, IOPL and the like) keep the values of the
+    /// tracee, and the addresses must point into user space.
+    fn apply(&self, uctx: &mut UserContext) -> AxResult<()> {
+        if [self.rip, self.rsp, self.fs_base, self.gs_base]
+            .iter()
+            .any(|&addr| !is_user_address(addr as usize))
+        {
+            return Err(AxError::from(LinuxError::EIO));
+        }
+        uctx.r15 = self.r15;
+        uctx.r14 = self.r14;
+        uctx.r13 = self.r13;
+        uctx.r12 = self.r12;
+        uctx.rbp = self.rbp;
+        uctx.rbx = self.rbx;
+        uctx.r11 = self.r11;
+        uctx.r10 = self.r10;
+        uctx.r9 = self.r9;
+        uctx.r8 = self.r8;
+        uctx.rax = self.rax;
+        uctx.rcx = self.rcx;
+        uctx.rdx = self.rdx;
+        uctx.rsi = self.rsi;
+        uctx.rdi = self.rdi;
+        uctx.rip = self.rip;
+        uctx.rflags = (uctx.rflags & !Self::FLAGS_MASK) | (self.eflags & Self::FLAGS_MASK);
+        uctx.rsp = self.rsp;
+        uctx.set_tls(self.fs_base as _);
+        uctx.gs_base = self.gs_base;
+        Ok(())
+    }
+}
+
+/// `struct user_regs_struct` of riscv64: the pc followed by `x1` to `x31`.
+#[cfg(target_arch = "riscv64")]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::AnyBitPattern)]
+struct UserRegs {
+    pc: usize,
+    regs: [usize; 31],
+}
+
+#[cfg(target_arch = "riscv64")]
+impl UserRegs {
+    fn from_context(uctx: &UserContext) -> Self {
+        let r = &uctx.regs;
+        Self {
+            pc: uctx.sepc,
+            regs: [
+                r.ra, r.sp, r.gp, r.tp, r.t0, r.t1, r.t2, r.s0, r.s1, r.a0, r.a1, r.a2, r.a3, r.a4,
+                r.a5, r.a6, r.a7, r.s2, r.s3, r.s4, r.s5, r.s6, r.s7, r.s8, r.s9, r.s10, r.s11,
+                r.t3, r.t4, r.t5, r.t6,
+            ],
+        }
+    }
+
+    /// Writes the registers back into `uctx`. `sstatus` is not part of the
+    /// block and keeps the value of the tracee.
+    fn apply(&self, uctx: &mut UserContext) -> AxResult<()> {
+        if !is_user_address(self.pc) {
+            return Err(AxError::from(LinuxError::EIO));
+        }
+        let r = &mut uctx.regs;
+        [
+            r.ra, r.sp, r.gp, r.tp, r.t0, r.t1, r.t2, r.s0, r.s1, r.a0, r.a1, r.a2, r.a3, r.a4,
+            r.a5, r.a6, r.a7, r.s2, r.s3, r.s4, r.s5, r.s6, r.s7, r.s8, r.s9, r.s10, r.s11, r.t3,
+            r.t4, r.t5, r.t6,
+        ] = self.regs;
+        uctx.sepc = self.pc;
+        Ok(())
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
+fn is_user_address(addr: usize) -> bool {
+    use starry_core::config::{USER_SPACE_BASE, USER_SPACE_SIZE};
+
+    (USER_SPACE_BASE..USER_SPACE_BASE + USER_SPACE_SIZE).contains(&addr)
+}
+
+/// Traced threads, by tid.
+static TRACEES: Mutex<BTreeMap<Pid, Arc<Tracee>>> = Mutex::new(BTreeMap::new());
+
+fn current_tracee() -> Option<Arc<Tracee>> {
+    TRACEES.lock().get(&(current().id().as_u64() as Pid)).cloned()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn set_single_step(uctx: &mut UserContext, enable: bool) {
+    const TF: u64 = 1 << 8;
+    if enable {
+        uctx.rflags |= TF;
+    } else {
+        uctx.rflags &= !TF;
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn set_single_step(_uctx: &mut UserContext, enable: bool) {
+    if enable {
+        warn!("ptrace: single-step is not supported on this architecture");
+    }
+}
+
+/// Stops the current thread for its tracer and blocks until it is resumed.
+/// The registers may be changed by the tracer in the meantime.
+///
+/// `SIGKILL` ends the stop without the tracer: the saved registers are then
+/// dropped, and the pending signal kills the thread on the way out.
+fn trace_stop(thr: &Thread, tracee: &Tracee, uctx: &mut UserContext, signo: Signo) {
+    set_single_step(uctx, false);
+    tracee.resumed.store(false, Ordering::Release);
+    *tracee.regs.lock() = Some(*uctx);
+
+    thr.proc_data.report_trace_stop(signo);
+    notify_job_change(&thr.proc_data, JobReport::Traced(signo));
+
+    let curr = current();
+    loop {
+        let res = block_on(interruptible(poll_fn(|cx| {
+            tracee.event.register(cx.waker());
+            if tracee.resumed.load(Ordering::Acquire) || thr.pending_exit() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })));
+        if res.is_ok() || thr.signal.pending().has(Signo::SIGKILL) {
+            break;
+        }
+        // Other signals wait until the tracer resumes the thread
+        curr.clear_interrupt();
+    }
+
+    let regs = tracee.regs.lock().take();
+    if !tracee.resumed.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(regs) = regs {
+        *uctx = regs;
+    }
+    if tracee.single_step.load(Ordering::Acquire) {
+        set_single_step(uctx, true);
+    }
+}
+
+/// Reports a `SIGTRAP` of the current thread to its tracer, if it is traced.
+///
+/// Returns `false` if the thread is not traced and the signal should be
+/// delivered as usual.
+pub fn ptrace_trap(thr: &Thread, uctx: &mut UserContext, signo: Signo) -> bool {
+    if signo != Signo::SIGTRAP {
+        return false;
+    }
+    let Some(tracee) = current_tracee() else {
+        return false;
+    };
+    trace_stop(thr, &tracee, uctx, signo);
+    true
+}
+
+/// Stops the current thread if a stop was requested by `PTRACE_ATTACH` or a
+/// traced `execve`.
+pub fn ptrace_check(thr: &Thread, uctx: &mut UserContext) {
+    let Some(tracee) = current_tracee() else {
+        return;
+    };
+    let pending = tracee.pending_stop.lock().take();
+    if let Some(signo) = pending {
+        trace_stop(thr, &tracee, uctx, signo);
+    }
+}
+
+/// Makes a traced thread stop with `SIGTRAP` once `execve` returns, so that
+/// its tracer gets control before the new program runs.
+pub fn ptrace_exec() {
+    if let Some(tracee) = current_tracee() {
+        *tracee.pending_stop.lock() = Some(Signo::SIGTRAP);
+    }
+}
+
+/// Drops the trace state of an exiting thread.
+pub fn ptrace_exit(tid: Pid) {
+    TRACEES.lock().remove(&tid);
+}
+
+/// Detaches every tracee of an exiting process, letting the stopped ones run
+/// again.
+pub fn ptrace_exit_tracer(pid: Pid) {
+    let mut detached = Vec::new();
+    TRACEES.lock().retain(|_, tracee| {
+        if tracee.tracer == pid {
+            detached.push(tracee.clone());
+            false
+        } else {
+            true
+        }
+    });
+    for tracee in detached {
+        tracee.resume(false);
+    }
+}
+
+/// Whether the current process traces one of the threads of `proc_data`.
+pub fn traces_process(proc_data: &ProcessData) -> bool {
+    let pid = current().as_thread().proc_data.proc.pid();
+    let threads = proc_data.proc.threads();
+    TRACEES
+        .lock()
+        .iter()
+        .any(|(tid, tracee)| tracee.tracer == pid && threads.contains(tid))
+}
+
+/// Looks up a tracee of the current process that is stopped.
+fn stopped_tracee(tid: Pid) -> AxResult<Arc<Tracee>> {
+    let pid = current().as_thread().proc_data.proc.pid();
+    let tracee = TRACEES
+        .lock()
+        .get(&tid)
+        .filter(|tracee| tracee.tracer == pid)
+        .cloned()
+        .ok_or(AxError::NoSuchProcess)?;
+    if tracee.resumed.load(Ordering::Acquire) || tracee.regs.lock().is_none() {
+        return Err(AxError::NoSuchProcess);
+    }
+    Ok(tracee)
+}
+
+pub fn sys_ptrace(request: u32, pid: Pid, addr: usize, data: usize) -> AxResult<isize> {
+    debug!("sys_ptrace <= request: {request}, pid: {pid}, addr: {addr:#x}, data: {data:#x}");
+
+    let curr = current();
+    let proc = &curr.as_thread().proc_data.proc;
+    match request {
+        PTRACE_TRACEME => {
+            let parent = proc.parent().ok_or(AxError::OperationNotPermitted)?;
+            let tid = curr.id().as_u64() as Pid;
+            let mut tracees = TRACEES.lock();
+            if tracees.contains_key(&tid) {
+                return Err(AxError::OperationNotPermitted);
+            }
+            tracees.insert(tid, Tracee::new(parent.pid()));
+        }
+        PTRACE_ATTACH => {
+            let task = get_task(pid)?;
+            let thr = task.try_as_thread().ok_or(AxError::OperationNotPermitted)?;
+            // Only children may be traced for now
+            if thr.proc_data.proc.parent().is_none_or(|p| p.pid() != proc.pid()) {
+                return Err(AxError::OperationNotPermitted);
+            }
+            let tracee = Tracee::new(proc.pid());
+            *tracee.pending_stop.lock() = Some(Signo::SIGSTOP);
+            {
+                let mut tracees = TRACEES.lock();
+                if tracees.contains_key(&pid) {
+                    return Err(AxError::OperationNotPermitted);
+                }
+                tracees.insert(pid, tracee);
+            }
+            task.interrupt();
+        }
+        #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
+        PTRACE_GETREGS => {
+            let regs = stopped_tracee(pid)?.regs.lock().unwrap();
+            (data as *mut UserRegs).vm_write(UserRegs::from_context(&regs))?;
+        }
+        #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
+        PTRACE_SETREGS => {
+            let tracee = stopped_tracee(pid)?;
+            let new = (data as *const UserRegs).vm_read()?;
+            let mut regs = tracee.regs.lock();
+            new.apply(regs.as_mut().ok_or(AxError::NoSuchProcess)?)?;
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "riscv64")))]
+        PTRACE_GETREGS | PTRACE_SETREGS => return Err(AxError::from(LinuxError::EIO)),
+        PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+            stopped_tracee(pid)?;
+            let aspace = get_task(pid)?.as_thread().proc_data.aspace.clone();
+            let mut word = [0; size_of::<usize>()];
+            if read_remote(&aspace, addr, &mut word) != word.len() {
+                return Err(AxError::BadAddress);
+            }
+            (data as *mut usize).vm_write(usize::from_ne_bytes(word))?;
+        }
+        PTRACE_POKETEXT | PTRACE_POKEDATA => {
+            stopped_tracee(pid)?;
+            let aspace = get_task(pid)?.as_thread().proc_data.aspace.clone();
+            let word = data.to_ne_bytes();
+            // Breakpoints go into read-only text, so the write is forced
+            if force_write_remote(&aspace, addr, &word) != word.len() {
+                return Err(AxError::BadAddress);
+            }
+        }
+        PTRACE_CONT | PTRACE_SINGLESTEP => {
+            // TODO: inject the signal given in `data`
+            stopped_tracee(pid)?.resume(request == PTRACE_SINGLESTEP);
+        }
+        PTRACE_DETACH => {
+            let tracee = stopped_tracee(pid)?;
+            TRACEES.lock().remove(&pid);
+            tracee.resume(false);
+        }
+        _ => {
+            warn!("sys_ptrace: unsupported request {request}");
+            return Err(AxError::InvalidInput);
+        }
+    }
+    Ok(0)
+}
@@ -1,4 +1,6 @@
-use axerrno::{AxError, AxResult};
+use alloc::boxed::Box;
+
+use axerrno::{AxError, AxResult, LinuxError};
 use axhal::time::TimeValue;
 use axtask::{
     AxCpuMask, current,
@@ -8,7 +10,7 @@ use linux_raw_sys::general::{
     __kernel_clockid_t, CLOCK_MONOTONIC, CLOCK_REALTIME, PRIO_PGRP, PRIO_PROCESS, PRIO_USER,
     SCHED_RR, TIMER_ABSTIME, timespec,
 };
-use starry_core::task::{get_process_data, get_process_group};
+use starry_core::task::{AsThread, get_process_data, get_process_group};
 use starry_vm::{VmMutPtr, VmPtr, vm_load, vm_write_slice};
 
 use crate::time::TimeValueLike;
@@ -18,16 +20,32 @@ pub fn sys_sched_yield() -> AxResult<isize> {
     Ok(0)
 }
 
-fn sleep_impl(clock: impl Fn() -> TimeValue, dur: TimeValue) -> TimeValue {
-    debug!("sleep_impl <= {dur:?}");
-
-    let start = clock();
+/// Sleeps until `deadline` as measured by `clock`.
+///
+/// When a signal cuts the sleep short, the time left is written to `rem` and
+/// the call fails with `ERESTART`, so that `restart_syscall(2)` sleeps until
+/// the same deadline.
+fn sleep_until(
+    clock: fn() -> TimeValue,
+    deadline: TimeValue,
+    rem: Option<usize>,
+) -> AxResult<isize> {
+    debug!("sleep_until <= {deadline:?}");
 
     // TODO: currently ignoring concrete clock type
-    // We detect EINTR manually if the slept time is not enough.
-    let _ = block_on(interruptible(sleep(dur)));
+    if block_on(interruptible(sleep(deadline.saturating_sub(clock())))).is_ok() {
+        return Ok(0);
+    }
 
-    clock() - start
+    let left = deadline.saturating_sub(clock());
+    debug!("sleep_until => rem: {left:?}");
+    if let Some(rem) = rem {
+        (rem as *mut timespec).vm_write(timespec::from_time_value(left))?;
+    }
+    current()
+        .as_thread()
+        .set_restart(Some(Box::new(move || sleep_until(clock, deadline, rem))));
+    Err(AxError::from(LinuxError::ERESTART))
 }
 
 /// Sleep some nanoseconds
@@ -36,17 +54,8 @@ pub fn sys_nanosleep(req: *const timespec, rem: *mut timespec) -> AxResult<isize
     let req = unsafe { req.vm_read_uninit()?.assume_init() }.try_into_time_value()?;
     debug!("sys_nanosleep <= req: {req:?}");
 
-    let actual = sleep_impl(axhal::time::monotonic_time, req);
-
-    if let Some(diff) = req.checked_sub(actual) {
-        debug!("sys_nanosleep => rem: {diff:?}");
-        if let Some(rem) = rem.nullable() {
-            rem.vm_write(timespec::from_time_value(diff))?;
-        }
-        Err(AxError::Interrupted)
-    } else {
-        Ok(0)
-    }
+    let clock = axhal::time::monotonic_time;
+    sleep_until(clock, clock() + req, rem.nullable().map(|rem| rem as usize))
 }
 
 pub fn sys_clock_nanosleep(
@@ -67,22 +76,11 @@ pub fn sys_clock_nanosleep(
     let req = unsafe { req.vm_read_uninit()?.assume_init() }.try_into_time_value()?;
     debug!("sys_clock_nanosleep <= clock_id: {clock_id}, flags: {flags}, req: {req:?}");
 
-    let dur = if flags & TIMER_ABSTIME != 0 {
-        req.saturating_sub(clock())
-    } else {
-        req
-    };
-
-    let actual = sleep_impl(clock, dur);
-
-    if let Some(diff) = dur.checked_sub(actual) {
-        debug!("sys_clock_nanosleep => rem: {diff:?}");
-        if let Some(rem) = rem.nullable() {
-            rem.vm_write(timespec::from_time_value(diff))?;
-        }
-        Err(AxError::Interrupted)
+    if flags & TIMER_ABSTIME != 0 {
+        // An absolute deadline is simply retried, so there is nothing to report
+        sleep_until(clock, req, None)
     } else {
-        Ok(0)
+        sleep_until(clock, clock() + req, rem.nullable().map(|rem| rem as usize))
     }
 }
 
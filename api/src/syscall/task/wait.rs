@@ -10,8 +10,9 @@ use bitflags::bitflags;
 use linux_raw_sys::general::{
     __WALL, __WCLONE, __WNOTHREAD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED,
 };
-use starry_core::task::AsThread;
+use starry_core::task::{AsThread, JobReport, get_process_data};
 use starry_process::{Pid, Process};
+use starry_signal::Signo;
 use starry_vm::{VmMutPtr, VmPtr};
 
 bitflags! {
@@ -77,12 +78,20 @@ pub fn sys_waitpid(pid: i32, exit_code: *mut i32, options: u32) -> AxResult<isiz
         WaitPid::Pgid(-pid as _)
     };
 
-    // FIXME: add back support for WALL & WCLONE, since ProcessData may drop before
-    // Process now.
+    // A "clone" child is one that does not report to its parent with SIGCHLD.
+    // ProcessData may drop before Process, so zombies whose data is already gone
+    // count as ordinary children.
+    let is_clone = |child: &Process| {
+        get_process_data(child.pid()).is_ok_and(|data| data.exit_signal != Some(Signo::SIGCHLD))
+    };
     let children = proc
         .children()
         .into_iter()
         .filter(|child| pid.apply(child))
+        .filter(|child| {
+            options.contains(WaitOptions::WALL)
+                || is_clone(child) == options.contains(WaitOptions::WCLONE)
+        })
         .collect::<Vec<_>>();
     if children.is_empty() {
         return Err(AxError::from(LinuxError::ECHILD));
@@ -97,6 +106,22 @@ pub fn sys_waitpid(pid: i32, exit_code: *mut i32, options: u32) -> AxResult<isiz
                 exit_code.vm_write(child.exit_code())?;
             }
             Ok(Some(child.pid() as _))
+        } else if let Some((child, report)) = children.iter().find_map(|child| {
+            let accept = |report: &JobReport| match report {
+                JobReport::Stopped(_) => options.contains(WaitOptions::WUNTRACED),
+                JobReport::Continued => options.contains(WaitOptions::WCONTINUED),
+                // Tracers always see the stops of their tracees
+                JobReport::Traced(_) => true,
+            };
+            get_process_data(child.pid())
+                .ok()?
+                .take_job_report(accept, options.contains(WaitOptions::WNOWAIT))
+                .map(|report| (child, report))
+        }) {
+            if let Some(exit_code) = exit_code.nullable() {
+                exit_code.vm_write(report.wait_status())?;
+            }
+            Ok(Some(child.pid() as _))
         } else if options.contains(WaitOptions::WNOHANG) {
             Ok(Some(0))
         } else {
@@ -104,13 +129,15 @@ pub fn sys_waitpid(pid: i32, exit_code: *mut i32, options: u32) -> AxResult<isiz
         }
     };
 
+    // `child_exit_event` wakes on any child exiting, stopping or continuing, so
+    // every wakeup re-checks the awaited children and goes back to sleep if
+    // none of them has anything to report. The waker is registered before
+    // checking so that an exit in between is not missed.
     block_on(interruptible(poll_fn(|cx| {
+        proc_data.child_exit_event.register(cx.waker());
         match check_children().transpose() {
             Some(res) => Poll::Ready(res),
-            None => {
-                proc_data.child_exit_event.register(cx.waker());
-                Poll::Pending
-            }
+            None => Poll::Pending,
         }
     })))?
 }
@@ -4,7 +4,10 @@ use axerrno::{AxError, AxResult};
 use axhal::uspace::{ExceptionKind, ReturnReason, UserContext};
 use axtask::{TaskInner, current};
 use bytemuck::AnyBitPattern;
-use linux_raw_sys::general::ROBUST_LIST_LIMIT;
+use linux_raw_sys::general::{
+    BUS_ADRALN, ILL_ILLOPC, ROBUST_LIST_LIMIT, SEGV_ACCERR, SEGV_MAPERR, SI_KERNEL, TRAP_BRKPT,
+};
+use spin::Once;
 use starry_core::{
     futex::FutexKey,
     shm::SHM_MANAGER,
@@ -15,14 +18,76 @@ use starry_core::{
     time::TimerState,
 };
 use starry_process::Pid;
-use starry_signal::{SignalInfo, Signo};
+use starry_signal::{DefaultSignalAction, SignalDisposition, SignalInfo, Signo};
 use starry_vm::{VmMutPtr, VmPtr};
 
 use crate::{
-    signal::{check_signals, unblock_next_signal},
-    syscall::handle_syscall,
+    file::lock::release_process_locks,
+    signal::{
+        check_signals, child_signal_info, fault_signal_info, prepare_restart,
+        unblock_next_signal, wait_while_stopped,
+    },
+    syscall::{handle_syscall, ptrace_check, ptrace_exit, ptrace_exit_tracer, ptrace_trap},
 };
 
+/// Observes fatal user faults right before the signal is raised.
+///
+/// This lets downstream code write a minidump or a structured log instead of
+/// the default console message, which is only printed with the `crash-log`
+/// feature.
+pub trait CrashHandler: Send + Sync {
+    /// Called with the faulting context, the fault address and the signal
+    /// about to be raised.
+    fn on_fault(&self, uctx: &UserContext, addr: usize, signo: Signo);
+}
+
+/// The default handler, which logs the fault to the console.
+#[cfg(feature = "crash-log")]
+struct LogCrashHandler;
+
+#[cfg(feature = "crash-log")]
+impl CrashHandler for LogCrashHandler {
+    fn on_fault(&self, uctx: &UserContext, addr: usize, signo: Signo) {
+        info!(
+            "{:?}: {signo:?} at {addr:#x}, ip={:#x}, sp={:#x}",
+            current().as_thread().proc_data.proc,
+            uctx.ip(),
+            uctx.sp()
+        );
+    }
+}
+
+static CRASH_HANDLER: Once<&'static dyn CrashHandler> = Once::new();
+
+/// Installs the handler for fatal user faults. Only the first call has an
+/// effect.
+pub fn set_crash_handler(handler: &'static dyn CrashHandler) {
+    CRASH_HANDLER.call_once(|| handler);
+}
+
+/// Passes the fault to the crash handler if `signo` is going to kill the
+/// process, i.e. it is neither caught nor has a harmless default action.
+fn report_crash(uctx: &UserContext, addr: usize, signo: Signo) {
+    let actions = current().as_thread().proc_data.signal.actions.lock();
+    if !matches!(actions[signo].disposition, SignalDisposition::Default)
+        || !matches!(
+            signo.default_action(),
+            DefaultSignalAction::Terminate | DefaultSignalAction::CoreDump
+        )
+    {
+        return;
+    }
+    drop(actions);
+
+    #[cfg(feature = "crash-log")]
+    let handler = Some(CRASH_HANDLER.get().copied().unwrap_or(&LogCrashHandler));
+    #[cfg(not(feature = "crash-log"))]
+    let handler = CRASH_HANDLER.get().copied();
+    if let Some(handler) = handler {
+        handler.on_fault(uctx, addr, signo);
+    }
+}
+
 /// Create a new user task.
 pub fn new_user_task(name: &str, mut uctx: UserContext, set_child_tid: usize) -> TaskInner {
     TaskInner::new(
@@ -42,38 +107,60 @@ pub fn new_user_task(name: &str, mut uctx: UserContext, set_child_tid: usize) ->
                 set_timer_state(&curr, TimerState::Kernel);
 
                 match reason {
-                    ReturnReason::Syscall => handle_syscall(&mut uctx),
+                    ReturnReason::Syscall => {
+                        handle_syscall(&mut uctx);
+                        prepare_restart(thr, &mut uctx);
+                    }
                     ReturnReason::PageFault(addr, flags) => {
-                        if !thr.proc_data.aspace.lock().handle_page_fault(addr, flags) {
-                            info!(
-                                "{:?}: segmentation fault at {:#x} {:?}",
-                                thr.proc_data.proc, addr, flags
-                            );
-                            raise_signal_fatal(SignalInfo::new_kernel(Signo::SIGSEGV))
-                                .expect("Failed to send SIGSEGV");
+                        let code = {
+                            let mut aspace = thr.proc_data.aspace.lock();
+                            if aspace.handle_page_fault(addr, flags) {
+                                None
+                            } else if aspace.find_area(addr).is_some() {
+                                Some(SEGV_ACCERR)
+                            } else {
+                                Some(SEGV_MAPERR)
+                            }
+                        };
+                        if let Some(code) = code {
+                            debug!("page fault at {addr:#x} {flags:?} is not recoverable");
+                            report_crash(&uctx, addr.as_usize(), Signo::SIGSEGV);
+                            raise_signal_fatal(fault_signal_info(
+                                Signo::SIGSEGV,
+                                code,
+                                addr.as_usize(),
+                            ))
+                            .expect("Failed to send SIGSEGV");
                         }
                     }
                     ReturnReason::Interrupt => {}
-                    #[allow(unused_labels)]
                     ReturnReason::Exception(exc_info) => 'exc: {
                         // TODO: detailed handling
-                        let signo = match exc_info.kind() {
+                        let (signo, code) = match exc_info.kind() {
                             ExceptionKind::Misaligned => {
                                 #[cfg(target_arch = "loongarch64")]
                                 if unsafe { uctx.emulate_unaligned() }.is_ok() {
                                     break 'exc;
                                 }
-                                Signo::SIGBUS
+                                (Signo::SIGBUS, BUS_ADRALN)
                             }
-                            ExceptionKind::Breakpoint => Signo::SIGTRAP,
-                            ExceptionKind::IllegalInstruction => Signo::SIGILL,
-                            _ => Signo::SIGTRAP,
+                            ExceptionKind::Breakpoint => (Signo::SIGTRAP, TRAP_BRKPT),
+                            ExceptionKind::IllegalInstruction => (Signo::SIGILL, ILL_ILLOPC),
+                            _ => (Signo::SIGTRAP, SI_KERNEL as u32),
                         };
-                        raise_signal_fatal(SignalInfo::new_kernel(signo))
+                        if ptrace_trap(thr, &mut uctx, signo) {
+                            break 'exc;
+                        }
+                        // The exception info does not carry the data address, so
+                        // report the faulting instruction like Linux does for
+                        // SIGILL and SIGTRAP.
+                        report_crash(&uctx, uctx.ip(), signo);
+                        raise_signal_fatal(fault_signal_info(signo, code, uctx.ip()))
                             .expect("Failed to send SIGTRAP");
                     }
                     r => {
                         warn!("Unexpected return reason: {r:?}");
+                        report_crash(&uctx, uctx.ip(), Signo::SIGSEGV);
                         raise_signal_fatal(SignalInfo::new_kernel(Signo::SIGSEGV))
                             .expect("Failed to send SIGSEGV");
                     }
@@ -82,6 +169,9 @@ pub fn new_user_task(name: &str, mut uctx: UserContext, set_child_tid: usize) ->
                 if !unblock_next_signal() {
                     while check_signals(thr, &mut uctx, None) {}
                 }
+                // Another thread may have stopped the whole process
+                wait_while_stopped(thr);
+                ptrace_check(thr, &mut uctx);
 
                 set_timer_state(&curr, TimerState::User);
                 curr.clear_interrupt();
@@ -175,12 +265,15 @@ pub fn do_exit(exit_code: i32, group_exit: bool) {
         warn!("exit robust list failed: {err:?}");
     }
 
+    ptrace_exit(curr.id().as_u64() as Pid);
+
     let process = &thr.proc_data.proc;
     if process.exit_thread(curr.id().as_u64() as Pid, exit_code) {
         process.exit();
         if let Some(parent) = process.parent() {
             if let Some(signo) = thr.proc_data.exit_signal {
-                let _ = send_signal_to_process(parent.pid(), Some(SignalInfo::new_kernel(signo)));
+                let sig = child_signal_info(signo, process.pid(), process.exit_code());
+                let _ = send_signal_to_process(parent.pid(), Some(sig));
             }
             if let Ok(data) = get_process_data(parent.pid()) {
                 data.child_exit_event.wake();
@@ -188,7 +281,9 @@ pub fn do_exit(exit_code: i32, group_exit: bool) {
         }
         thr.proc_data.exit_event.wake();
 
+        ptrace_exit_tracer(process.pid());
         SHM_MANAGER.lock().clear_proc_shm(process.pid());
+        release_process_locks(process.pid());
     }
     if group_exit && !process.is_group_exited() {
         process.group_exit();
@@ -141,6 +141,28 @@ impl DeviceOps for CpuDmaLatency {
     }
 }
 
+/// `/dev/ioring`. Opening it creates a new [`IoRing`](crate::file::ioring::IoRing)
+/// instead of a file on the device.
+pub struct IoRingDevice;
+
+impl DeviceOps for IoRingDevice {
+    fn read_at(&self, _buf: &mut [u8], _offset: u64) -> VfsResult<usize> {
+        Err(AxError::InvalidInput)
+    }
+
+    fn write_at(&self, _buf: &[u8], _offset: u64) -> VfsResult<usize> {
+        Err(AxError::InvalidInput)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn flags(&self) -> NodeFlags {
+        NodeFlags::NON_CACHEABLE | NodeFlags::STREAM
+    }
+}
+
 fn builder(fs: Arc<SimpleFs>) -> DirMaker {
     let mut root = DirMapping::new();
     root.add(
@@ -267,6 +289,15 @@ fn builder(fs: Arc<SimpleFs>) -> DirMaker {
             Arc::new(CpuDmaLatency),
         ),
     );
+    root.add(
+        "ioring",
+        Device::new(
+            fs.clone(),
+            NodeType::CharacterDevice,
+            DeviceId::new(10, 1025),
+            Arc::new(IoRingDevice),
+        ),
+    );
 
     // This is mounted to a tmpfs in `new_procfs`
     root.add(
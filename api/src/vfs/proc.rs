@@ -7,12 +7,16 @@ use alloc::{
     vec,
     vec::Vec,
 };
-use core::{ffi::CStr, iter};
+use core::{ffi::CStr, fmt::Write, iter};
 
 use axfs_ng_vfs::{Filesystem, NodeType, VfsError, VfsResult};
+use axhal::paging::MappingFlags;
+use axmm::backend::Backend;
 use axtask::{AxTaskRef, WeakAxTaskRef, current};
 use indoc::indoc;
 use starry_core::{
+    config::{SIGNAL_TRAMPOLINE, USER_HEAP_BASE, USER_STACK_TOP},
+    mm::file_maps,
     task::{AsThread, TaskStat, get_task, tasks},
     vfs::{
         DirMaker, DirMapping, NodeOpsMux, RwFile, SimpleDir, SimpleDirOps, SimpleFile,
@@ -143,6 +147,66 @@ fn task_status(task: &AxTaskRef) -> String {
     )
 }
 
+/// Formats the user mappings of `task`'s address space like Linux's
+/// `/proc/[pid]/maps`.
+fn task_maps(task: &AxTaskRef) -> String {
+    let proc_data = &task.as_thread().proc_data;
+    let file_maps = file_maps(&proc_data.aspace);
+    let aspace = proc_data.aspace.lock();
+    let mut maps = String::new();
+    for area in aspace.areas() {
+        let flags = area.flags();
+        if !flags.contains(MappingFlags::USER) {
+            continue;
+        }
+        let perm = |flag, ch| if flags.contains(flag) { ch } else { '-' };
+        // Only shared file mappings use the file backend, private ones are
+        // copy-on-write
+        let shared = if matches!(area.backend(), Backend::File(_)) {
+            's'
+        } else {
+            'p'
+        };
+        let file = file_maps
+            .iter()
+            .find(|map| map.start <= area.start() && area.start() < map.end);
+        let (offset, dev, ino) = file.map_or((0, 0, 0), |map| {
+            (
+                map.offset + (area.start() - map.start) as u64,
+                map.dev,
+                map.ino,
+            )
+        });
+        let name = if let Some(map) = file {
+            map.path.as_str()
+        } else if area.start().as_usize() == SIGNAL_TRAMPOLINE {
+            "[vdso]"
+        } else if area.start().as_usize() == USER_HEAP_BASE {
+            "[heap]"
+        } else if area.end().as_usize() == USER_STACK_TOP {
+            "[stack]"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            maps,
+            "{:08x}-{:08x} {}{}{}{} {:08x} {:02x}:{:02x} {:<10} {}",
+            area.start().as_usize(),
+            area.end().as_usize(),
+            perm(MappingFlags::READ, 'r'),
+            perm(MappingFlags::WRITE, 'w'),
+            perm(MappingFlags::EXECUTE, 'x'),
+            shared,
+            offset,
+            dev >> 32,
+            dev as u32,
+            ino,
+            name,
+        );
+    }
+    maps
+}
+
 /// The /proc/[pid]/fd directory
 struct ThreadFdDir {
     fs: Arc<SimpleFs>,
@@ -245,15 +309,7 @@ impl SimpleDirOps for ThreadDir {
                 }),
             )
             .into(),
-            "maps" => SimpleFile::new_regular(fs, move || {
-                Ok(indoc! {"
-                    7f000000-7f001000 r--p 00000000 00:00 0          [vdso]
-                    7f001000-7f003000 r-xp 00001000 00:00 0          [vdso]
-                    7f003000-7f005000 r--p 00003000 00:00 0          [vdso]
-                    7f005000-7f007000 rw-p 00005000 00:00 0          [vdso]
-                "})
-            })
-            .into(),
+            "maps" => SimpleFile::new_regular(fs, move || Ok(task_maps(&task))).into(),
             "mounts" => SimpleFile::new_regular(fs, move || {
                 Ok("proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n")
             })
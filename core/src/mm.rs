@@ -1,6 +1,12 @@
 //! User address space management.
 
-use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
 use core::{ffi::CStr, hint::unlikely, iter, mem::MaybeUninit};
 
 use axerrno::{AxError, AxResult};
@@ -48,6 +54,109 @@ pub fn copy_from_kernel(_aspace: &mut AddrSpace) -> AxResult {
     Ok(())
 }
 
+/// A file mapped over `[start, end)` of an address space, as listed in
+/// `/proc/[pid]/maps`.
+#[derive(Debug, Clone)]
+pub struct FileMap {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    /// The file offset mapped at `start`.
+    pub offset: u64,
+    pub dev: u64,
+    pub ino: u64,
+    pub path: String,
+}
+
+struct FileMapEntry {
+    aspace: Weak<Mutex<AddrSpace>>,
+    map: FileMap,
+}
+
+impl FileMapEntry {
+    fn is_in(&self, aspace: &Arc<Mutex<AddrSpace>>) -> bool {
+        self.aspace.as_ptr() == Arc::as_ptr(aspace)
+    }
+}
+
+/// File mappings of all user address spaces. `mmap`, `munmap`, `execve` and
+/// `fork` keep this in step with the address spaces, and entries of dropped
+/// address spaces are pruned whenever a mapping is added.
+static FILE_MAPS: Mutex<Vec<FileMapEntry>> = Mutex::new(Vec::new());
+
+/// Records that `map` has been mapped into `aspace`.
+pub fn add_file_map(aspace: &Arc<Mutex<AddrSpace>>, map: FileMap) {
+    let mut maps = FILE_MAPS.lock();
+    maps.retain(|entry| entry.aspace.strong_count() > 0);
+    maps.push(FileMapEntry {
+        aspace: Arc::downgrade(aspace),
+        map,
+    });
+}
+
+/// Forgets the file mappings of `aspace` within `[start, start + len)`, once
+/// that range has been unmapped.
+pub fn forget_file_maps(aspace: &Arc<Mutex<AddrSpace>>, start: VirtAddr, len: usize) {
+    let end = start + len;
+    let mut maps = FILE_MAPS.lock();
+    let mut rest = Vec::new();
+    maps.retain(|entry| {
+        let map = &entry.map;
+        if !entry.is_in(aspace) || map.end <= start || end <= map.start {
+            return true;
+        }
+        if map.start < start {
+            rest.push(FileMapEntry {
+                aspace: entry.aspace.clone(),
+                map: FileMap {
+                    end: start,
+                    ..map.clone()
+                },
+            });
+        }
+        if end < map.end {
+            rest.push(FileMapEntry {
+                aspace: entry.aspace.clone(),
+                map: FileMap {
+                    start: end,
+                    offset: map.offset + (end - map.start) as u64,
+                    ..map.clone()
+                },
+            });
+        }
+        false
+    });
+    maps.extend(rest);
+}
+
+/// Forgets all file mappings of `aspace`, once it has been cleared.
+pub fn forget_all_file_maps(aspace: &Arc<Mutex<AddrSpace>>) {
+    FILE_MAPS.lock().retain(|entry| !entry.is_in(aspace));
+}
+
+/// Gives the forked address space `child` the file mappings of `parent`.
+pub fn fork_file_maps(parent: &Arc<Mutex<AddrSpace>>, child: &Arc<Mutex<AddrSpace>>) {
+    let mut maps = FILE_MAPS.lock();
+    let inherited: Vec<_> = maps
+        .iter()
+        .filter(|entry| entry.is_in(parent))
+        .map(|entry| FileMapEntry {
+            aspace: Arc::downgrade(child),
+            map: entry.map.clone(),
+        })
+        .collect();
+    maps.extend(inherited);
+}
+
+/// Returns the file mappings of `aspace`.
+pub fn file_maps(aspace: &Arc<Mutex<AddrSpace>>) -> Vec<FileMap> {
+    FILE_MAPS
+        .lock()
+        .iter()
+        .filter(|entry| entry.is_in(aspace))
+        .map(|entry| entry.map.clone())
+        .collect()
+}
+
 /// Map the signal trampoline to the user address space.
 pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
     let signal_trampoline_paddr =
@@ -79,17 +188,21 @@ fn mapping_flags(flags: xmas_elf::program::Flags) -> MappingFlags {
 ///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
+/// - `maps`: Receives the mapped segments.
 /// - `elf`: The elf file.
 ///
 /// # Returns
 /// - The entry point of the user app.
 fn map_elf<'a>(
     uspace: &mut AddrSpace,
+    maps: &mut Vec<FileMap>,
     base: usize,
     entry: &'a ElfCacheEntry,
 ) -> AxResult<ELFParser<'a>> {
     let elf_parser = ELFParser::new(entry.borrow_elf(), base).map_err(|_| AxError::InvalidData)?;
     let cache = entry.borrow_cache();
+    let metadata = cache.location().metadata()?;
+    let path = cache.location().absolute_path()?.to_string();
 
     for ph in elf_parser
         .headers()
@@ -127,6 +240,14 @@ fn map_elf<'a>(
             false,
             backend,
         )?;
+        maps.push(FileMap {
+            start: seg_start.align_down_4k(),
+            end: seg_start.align_down_4k() + seg_align_size,
+            offset: ph.offset - seg_pad as u64,
+            dev: metadata.device,
+            ino: metadata.inode,
+            path: path.clone(),
+        });
 
         // TDOO: flush the I-cache
     }
@@ -182,7 +303,12 @@ impl ElfLoader {
         Self(LRUCache::new())
     }
 
-    fn load(&mut self, uspace: &mut AddrSpace, path: &str) -> AxResult<LoadResult> {
+    fn load(
+        &mut self,
+        uspace: &mut AddrSpace,
+        maps: &mut Vec<FileMap>,
+        path: &str,
+    ) -> AxResult<LoadResult> {
         let loc = FS_CONTEXT.lock().resolve(path)?;
 
         if !self.0.touch(|e| e.borrow_cache().location().ptr_eq(&loc)) {
@@ -236,9 +362,9 @@ impl ElfLoader {
             (entry, None)
         };
 
-        let elf = map_elf(uspace, crate::config::USER_SPACE_BASE, elf)?;
+        let elf = map_elf(uspace, maps, crate::config::USER_SPACE_BASE, elf)?;
         let ldso = ldso
-            .map(|elf| map_elf(uspace, crate::config::USER_INTERP_BASE, elf))
+            .map(|elf| map_elf(uspace, maps, crate::config::USER_INTERP_BASE, elf))
             .transpose()?;
 
         let entry = VirtAddr::from_usize(
@@ -265,7 +391,7 @@ pub fn clear_elf_cache() {
 /// Load the user app to the user address space.
 ///
 /// # Arguments
-/// - `uspace`: The address space of the user app.
+/// - `aspace`: The address space of the user app.
 /// - `args`: The arguments of the user app. The first argument is the path of
 ///   the user app.
 /// - `envs`: The environment variables of the user app.
@@ -274,7 +400,23 @@ pub fn clear_elf_cache() {
 /// - The entry point of the user app.
 /// - The stack pointer of the user app.
 pub fn load_user_app(
+    aspace: &Arc<Mutex<AddrSpace>>,
+    path: Option<&str>,
+    args: &[String],
+    envs: &[String],
+) -> AxResult<(VirtAddr, VirtAddr)> {
+    let mut maps = Vec::new();
+    let (entry, user_sp) = load_app(&mut aspace.lock(), &mut maps, path, args, envs)?;
+    forget_all_file_maps(aspace);
+    for map in maps {
+        add_file_map(aspace, map);
+    }
+    Ok((entry, user_sp))
+}
+
+fn load_app(
     uspace: &mut AddrSpace,
+    maps: &mut Vec<FileMap>,
     path: Option<&str>,
     args: &[String],
     envs: &[String],
@@ -288,10 +430,10 @@ pub fn load_user_app(
         let new_args: Vec<String> = iter::once("/bin/sh".to_owned())
             .chain(args.iter().cloned())
             .collect();
-        return load_user_app(uspace, None, &new_args, envs);
+        return load_app(uspace, maps, None, &new_args, envs);
     }
 
-    let (entry, auxv) = match { ELF_LOADER.lock().load(uspace, path)? } {
+    let (entry, auxv) = match { ELF_LOADER.lock().load(uspace, maps, path)? } {
         Ok((entry, auxv)) => (entry, auxv),
         Err(data) => {
             if data.starts_with(b"#!") {
@@ -306,7 +448,7 @@ pub fn load_user_app(
                     .chain(iter::once(path.to_owned()))
                     .chain(args.iter().skip(1).cloned())
                     .collect();
-                return load_user_app(uspace, None, &new_args, envs);
+                return load_app(uspace, maps, None, &new_args, envs);
             }
             return Err(AxError::InvalidExecutable);
         }
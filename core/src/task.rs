@@ -11,17 +11,19 @@ use alloc::{
 use core::{
     cell::RefCell,
     ops::Deref,
-    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
 };
 
 use axerrno::{AxError, AxResult};
 use axmm::AddrSpace;
 use axpoll::PollSet;
 use axsync::{Mutex, spin::SpinNoIrq};
-use axtask::{AxTaskRef, TaskExt, TaskInner, WeakAxTaskRef, current};
+use axtask::{AxTaskRef, TaskExt, TaskInner, TaskState, WeakAxTaskRef, current};
 use extern_trait::extern_trait;
 use hashbrown::HashMap;
 use lazy_static::lazy_static;
+use linux_raw_sys::general::{CLD_CONTINUED, CLD_STOPPED, CLD_TRAPPED};
 use scope_local::{ActiveScope, Scope};
 use spin::RwLock;
 use starry_process::{Pid, Process, ProcessGroup, Session};
@@ -85,8 +87,21 @@ pub struct Thread {
 
     /// Indicates whether the thread is currently accessing user memory.
     accessing_user_memory: AtomicBool,
+
+    /// Context switches made by blocking.
+    voluntary_switches: AtomicU64,
+
+    /// Context switches made while still runnable, by preemption or yielding.
+    involuntary_switches: AtomicU64,
+
+    /// Resumes the system call last interrupted by a signal, see
+    /// `restart_syscall(2)`.
+    restart: SpinNoIrq<Option<RestartFn>>,
 }
 
+/// Resumes an interrupted system call, see [`Thread::set_restart`].
+pub type RestartFn = Box<dyn FnOnce() -> AxResult<isize> + Send>;
+
 impl Thread {
     /// Create a new [`Thread`].
     pub fn new(tid: u32, proc_data: Arc<ProcessData>) -> Box<Self> {
@@ -99,6 +114,9 @@ impl Thread {
             oom_score_adj: AtomicI32::new(200),
             exit: AtomicBool::new(false),
             accessing_user_memory: AtomicBool::new(false),
+            voluntary_switches: AtomicU64::new(0),
+            involuntary_switches: AtomicU64::new(0),
+            restart: SpinNoIrq::new(None),
         })
     }
 
@@ -154,6 +172,25 @@ impl Thread {
         self.accessing_user_memory
             .store(accessing, Ordering::Release);
     }
+
+    /// Sets how `restart_syscall(2)` resumes the system call that is being
+    /// interrupted.
+    pub fn set_restart(&self, restart: Option<RestartFn>) {
+        *self.restart.lock() = restart;
+    }
+
+    /// Takes the function set by [`Thread::set_restart`].
+    pub fn take_restart(&self) -> Option<RestartFn> {
+        self.restart.lock().take()
+    }
+
+    /// Get the number of voluntary and involuntary context switches.
+    pub fn context_switches(&self) -> (u64, u64) {
+        (
+            self.voluntary_switches.load(Ordering::Relaxed),
+            self.involuntary_switches.load(Ordering::Relaxed),
+        )
+    }
 }
 
 #[extern_trait]
@@ -165,6 +202,20 @@ unsafe impl TaskExt for Box<Thread> {
     }
 
     fn on_leave(&self) {
+        // Like Linux, only a thread that blocks gives up the CPU voluntarily.
+        // One that is still runnable, whether preempted in user space or in
+        // the kernel, or yielding, is switched out involuntarily.
+        let curr = current();
+        let runnable = curr
+            .try_as_thread()
+            .is_some_and(|thr| ptr::eq(thr, &**self))
+            && matches!(curr.state(), TaskState::Running | TaskState::Ready);
+        if runnable {
+            self.involuntary_switches.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.voluntary_switches.fetch_add(1, Ordering::Relaxed);
+        }
+
         ActiveScope::set_global();
         unsafe { self.proc_data.scope.force_read_decrement() };
     }
@@ -222,6 +273,46 @@ pub struct ProcessData {
 
     /// The default mask for file permissions.
     umask: AtomicU32,
+
+    /// Whether the process is stopped by a job-control signal.
+    stopped: AtomicBool,
+    /// Woken when a stopped process is continued.
+    pub stop_event: Arc<PollSet>,
+    /// Job-control changes not yet reported through `waitpid`.
+    job_reports: SpinNoIrq<PendingJobReports>,
+    /// Whether the `SIGCHLD` action has `SA_NOCLDSTOP`, which
+    /// [`SignalActions`] does not keep.
+    no_child_stop: AtomicBool,
+}
+
+/// Unreported job-control changes of a process, one slot per kind so that a
+/// later change does not hide an earlier one.
+#[derive(Default)]
+struct PendingJobReports {
+    traced: Option<Signo>,
+    stopped: Option<Signo>,
+    continued: bool,
+}
+
+/// A job-control state change of a process, as reported to its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobReport {
+    /// Stopped by the given signal.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+    /// Stopped by the given signal under `ptrace`.
+    Traced(Signo),
+}
+
+impl JobReport {
+    /// Returns the wait status `waitpid` reports for this change.
+    pub fn wait_status(&self) -> i32 {
+        match self {
+            JobReport::Stopped(signo) | JobReport::Traced(signo) => ((*signo as i32) << 8) | 0x7f,
+            JobReport::Continued => 0xffff,
+        }
+    }
 }
 
 impl ProcessData {
@@ -256,6 +347,11 @@ impl ProcessData {
             futex_table: Arc::new(FutexTable::new()),
 
             umask: AtomicU32::new(0o022),
+
+            stopped: AtomicBool::new(false),
+            stop_event: Arc::default(),
+            job_reports: SpinNoIrq::new(PendingJobReports::default()),
+            no_child_stop: AtomicBool::new(false),
         })
     }
 
@@ -289,6 +385,74 @@ impl ProcessData {
         }
     }
 
+    /// Returns whether the process is stopped by a job-control signal.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stops the process because of `signo`.
+    ///
+    /// Returns `false` if it was already stopped.
+    pub fn stop(&self, signo: Signo) -> bool {
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        // A continue the parent has not collected yet is stale now
+        let mut reports = self.job_reports.lock();
+        reports.stopped = Some(signo);
+        reports.continued = false;
+        true
+    }
+
+    /// Continues a stopped process and wakes its threads. Unless `report` is
+    /// unset, the continue is recorded for `waitpid`, replacing a stop the
+    /// parent has not collected yet.
+    ///
+    /// Returns `false` if it was not stopped.
+    pub fn resume(&self, report: bool) -> bool {
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        if report {
+            let mut reports = self.job_reports.lock();
+            reports.stopped = None;
+            reports.continued = true;
+        }
+        self.stop_event.wake();
+        true
+    }
+
+    /// Records a `ptrace` stop of one of the threads, to be reported to the
+    /// tracer through `waitpid`.
+    pub fn report_trace_stop(&self, signo: Signo) {
+        self.job_reports.lock().traced = Some(signo);
+    }
+
+    /// Returns the first pending job-control report that `accept` selects,
+    /// taking it unless `keep` is set. Trace stops come first, then stops,
+    /// then continues.
+    pub fn take_job_report(
+        &self,
+        accept: impl Fn(&JobReport) -> bool,
+        keep: bool,
+    ) -> Option<JobReport> {
+        let mut reports = self.job_reports.lock();
+        let candidates = [
+            reports.traced.map(JobReport::Traced),
+            reports.stopped.map(JobReport::Stopped),
+            reports.continued.then_some(JobReport::Continued),
+        ];
+        let report = candidates.into_iter().flatten().find(&accept)?;
+        if !keep {
+            match report {
+                JobReport::Traced(_) => reports.traced = None,
+                JobReport::Stopped(_) => reports.stopped = None,
+                JobReport::Continued => reports.continued = false,
+            }
+        }
+        Some(report)
+    }
+
     /// Get the umask.
     pub fn umask(&self) -> u32 {
         self.umask.load(Ordering::SeqCst)
@@ -299,6 +463,16 @@ impl ProcessData {
         self.umask.store(umask, Ordering::SeqCst);
     }
 
+    /// Returns whether the `SIGCHLD` action has `SA_NOCLDSTOP`.
+    pub fn no_child_stop(&self) -> bool {
+        self.no_child_stop.load(Ordering::Acquire)
+    }
+
+    /// Records whether the `SIGCHLD` action has `SA_NOCLDSTOP`.
+    pub fn set_no_child_stop(&self, no_child_stop: bool) {
+        self.no_child_stop.store(no_child_stop, Ordering::Release);
+    }
+
     /// Set the umask and return the old value.
     pub fn replace_umask(&self, umask: u32) -> u32 {
         self.umask.swap(umask, Ordering::SeqCst)
@@ -460,6 +634,82 @@ fn send_signal_thread_inner(task: &TaskInner, thr: &Thread, sig: SignalInfo) {
     }
 }
 
+/// The signal-specific details of a kernel-generated [`SignalInfo`].
+pub enum SignalFields {
+    /// The faulting address of `SIGSEGV`, `SIGBUS`, `SIGILL` or `SIGTRAP`.
+    Fault { addr: usize },
+    /// The child and its status, for `SIGCHLD`.
+    Child { pid: Pid, status: i32 },
+}
+
+/// Builds a kernel-generated `SignalInfo` with `si_code` and the details in
+/// `fields` filled in.
+pub fn kernel_signal_info(signo: Signo, code: u32, fields: SignalFields) -> SignalInfo {
+    let mut sig = SignalInfo::new_kernel(signo);
+    // SAFETY: The outer union only overlays the header struct with padding,
+    // so the header arm is always valid. Of `_sifields`, `_sigfault` is the
+    // arm read for fault signals and `_sigchld` the one read for `SIGCHLD`,
+    // which is what `fields` selects. All of them are plain integers and
+    // pointers, so any bit pattern is valid.
+    let info = unsafe { &mut sig.0.__bindgen_anon_1.__bindgen_anon_1 };
+    info.si_code = code as _;
+    match fields {
+        SignalFields::Fault { addr } => {
+            info._sifields._sigfault._addr = addr as _;
+        }
+        SignalFields::Child { pid, status } => {
+            info._sifields._sigchld._pid = pid as _;
+            info._sifields._sigchld._status = status;
+        }
+    }
+    sig
+}
+
+/// Builds the `SIGCHLD` sent to the parent of `pid` on a job-control change.
+pub fn job_signal_info(pid: Pid, report: JobReport) -> SignalInfo {
+    let (code, status) = match report {
+        JobReport::Stopped(signo) => (CLD_STOPPED, signo as i32),
+        JobReport::Continued => (CLD_CONTINUED, Signo::SIGCONT as i32),
+        JobReport::Traced(signo) => (CLD_TRAPPED, signo as i32),
+    };
+    kernel_signal_info(Signo::SIGCHLD, code, SignalFields::Child { pid, status })
+}
+
+/// Tells the parent of `proc_data` about a job-control change. No `SIGCHLD`
+/// is sent if the parent asked for none with `SA_NOCLDSTOP`, but `waitpid`
+/// still sees the change.
+pub fn notify_job_change(proc_data: &ProcessData, report: JobReport) {
+    let Some(parent) = proc_data.proc.parent() else {
+        return;
+    };
+    let Ok(data) = get_process_data(parent.pid()) else {
+        return;
+    };
+    if !data.no_child_stop() {
+        let _ = send_signal_to_process(
+            parent.pid(),
+            Some(job_signal_info(proc_data.proc.pid(), report)),
+        );
+    }
+    data.child_exit_event.wake();
+}
+
+/// `SIGCONT` continues a stopped process as soon as it is sent, and `SIGKILL`
+/// has to wake it so that it can die, without reporting a continue.
+fn continue_on_signal(proc_data: &ProcessData, signo: Signo) {
+    match signo {
+        Signo::SIGCONT => {
+            if proc_data.resume(true) {
+                notify_job_change(proc_data, JobReport::Continued);
+            }
+        }
+        Signo::SIGKILL => {
+            proc_data.resume(false);
+        }
+        _ => {}
+    }
+}
+
 /// Sends a signal to a thread.
 pub fn send_signal_to_thread(tgid: Option<Pid>, tid: Pid, sig: Option<SignalInfo>) -> AxResult<()> {
     let task = get_task(tid)?;
@@ -470,6 +720,7 @@ pub fn send_signal_to_thread(tgid: Option<Pid>, tid: Pid, sig: Option<SignalInfo
 
     if let Some(sig) = sig {
         info!("Send signal {:?} to thread {}", sig.signo(), tid);
+        continue_on_signal(&thread.proc_data, sig.signo());
         send_signal_thread_inner(&task, thread, sig);
     }
 
@@ -483,6 +734,7 @@ pub fn send_signal_to_process(pid: Pid, sig: Option<SignalInfo>) -> AxResult<()>
     if let Some(sig) = sig {
         let signo = sig.signo();
         info!("Send signal {signo:?} to process {pid}");
+        continue_on_signal(&proc_data, signo);
         if let Some(tid) = proc_data.signal.send_signal(sig)
             && let Ok(task) = get_task(tid)
         {
@@ -494,8 +746,15 @@ pub fn send_signal_to_process(pid: Pid, sig: Option<SignalInfo>) -> AxResult<()>
 }
 
 /// Sends a signal to a process group.
+///
+/// A `pgid` of 0 refers to the process group of the caller.
 pub fn send_signal_to_process_group(pgid: Pid, sig: Option<SignalInfo>) -> AxResult<()> {
-    let pg = get_process_group(pgid)?;
+    let pg = if pgid == 0 {
+        current().as_thread().proc_data.proc.group()
+    } else {
+        get_process_group(pgid)?
+    };
+    let pgid = pg.pgid();
 
     if let Some(sig) = sig {
         info!("Send signal {:?} to process group {}", sig.signo(), pgid);
@@ -506,3 +765,21 @@ pub fn send_signal_to_process_group(pgid: Pid, sig: Option<SignalInfo>) -> AxRes
 
     Ok(())
 }
+
+/// Sends a signal to every process except init and the caller.
+///
+/// This is what `kill(-1, sig)` does on Linux.
+pub fn send_signal_to_all(sig: Option<SignalInfo>) -> AxResult<()> {
+    let Some(sig) = sig else {
+        return Ok(());
+    };
+    let curr_pid = current().as_thread().proc_data.proc.pid();
+    info!("Send signal {:?} to all processes", sig.signo());
+    for proc_data in processes() {
+        if proc_data.proc.is_init() || proc_data.proc.pid() == curr_pid {
+            continue;
+        }
+        let _ = send_signal_to_process(proc_data.proc.pid(), Some(sig.clone()));
+    }
+    Ok(())
+}
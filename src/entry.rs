@@ -15,10 +15,10 @@ use starry_core::{
 use starry_process::{Pid, Process};
 
 pub fn run_initproc(args: &[String], envs: &[String]) -> i32 {
-    let mut uspace = new_user_aspace_empty()
+    let uspace = new_user_aspace_empty()
         .and_then(|mut it| {
             copy_from_kernel(&mut it)?;
-            Ok(it)
+            Ok(Arc::new(Mutex::new(it)))
         })
         .expect("Failed to create user address space");
 
@@ -31,13 +31,13 @@ pub fn run_initproc(args: &[String], envs: &[String]) -> i32 {
         .expect("Failed to get executable absolute path");
     let name = loc.name();
 
-    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, None, args, envs)
+    let (entry_vaddr, ustack_top) = load_user_app(&uspace, None, args, envs)
         .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
 
     let uctx = UserContext::new(entry_vaddr.into(), ustack_top, 0);
 
     let mut task = new_user_task(name, uctx, 0);
-    task.ctx_mut().set_page_table_root(uspace.page_table_root());
+    task.ctx_mut().set_page_table_root(uspace.lock().page_table_root());
 
     let pid = task.id().as_u64() as Pid;
     let proc = Process::new_init(pid);
@@ -49,7 +49,7 @@ pub fn run_initproc(args: &[String], envs: &[String]) -> i32 {
         proc,
         path.to_string(),
         Arc::new(args.to_vec()),
-        Arc::new(Mutex::new(uspace)),
+        uspace,
         Arc::default(),
         None,
     );